@@ -1,3 +1,5 @@
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn main() {
@@ -51,4 +53,38 @@ fn main() {
         .format("%Y-%m-%d %H:%M:%S UTC")
         .to_string();
     println!("cargo:rustc-env=BUILD_TIME={}", build_time);
+
+    // Template engine versions, so an engine upgrade invalidates the build cache
+    let lock_file = find_lock_file();
+    if let Some(path) = &lock_file {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    let lock = lock_file
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default();
+    for (name, var) in [
+        ("handlebars", "CANON_HANDLEBARS_VERSION"),
+        ("tera", "CANON_TERA_VERSION"),
+    ] {
+        let version = locked_version(&lock, name).unwrap_or_else(|| "unknown".to_string());
+        println!("cargo:rustc-env={}={}", var, version);
+    }
+}
+
+/// Cargo.lock of this crate or the workspace containing it
+fn find_lock_file() -> Option<PathBuf> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").ok()?;
+    Path::new(&manifest_dir)
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.exists())
+}
+
+/// Version of package `name` recorded in a Cargo.lock
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let name_line = format!("name = \"{}\"", name);
+    let mut lines = lock.lines();
+    lines.find(|line| line.trim() == name_line)?;
+    let version = lines.next()?.trim().strip_prefix("version = ")?;
+    Some(version.trim_matches('"').to_string())
 }
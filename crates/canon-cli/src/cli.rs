@@ -23,6 +23,10 @@ pub struct Cli {
     #[arg(long, global = true, value_name = "URL")]
     pub registry: Option<String>,
 
+    /// Never use the network; install and validate from .canon/ and the package cache
+    #[arg(long, global = true)]
+    pub offline: bool,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -41,7 +45,23 @@ pub enum Commands {
     },
 
     /// Install dependencies from canon.yml
-    Install,
+    Install {
+        /// Require canon.lock to be present and up to date with canon.yml
+        #[arg(long)]
+        locked: bool,
+
+        /// Like --locked, and also refuse to fetch anything not already installed
+        #[arg(long)]
+        frozen: bool,
+
+        /// Accept packages without a verifiable manifest and signature (legacy specs)
+        #[arg(long)]
+        allow_unsigned: bool,
+
+        /// Number of packages to fetch at once (default: 8)
+        #[arg(long, value_name = "N")]
+        parallel: Option<usize>,
+    },
 
     /// Add a new dependency to canon.yml
     Add {
@@ -49,6 +69,62 @@ pub enum Commands {
         uri: String,
     },
 
+    /// Update dependencies to the newest versions their ranges allow
+    Update {
+        /// Only update these packages (publisher/id); all of them when omitted
+        uris: Vec<String>,
+
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also move past the ranges in canon.yml, rewriting them to the newest release
+        #[arg(long)]
+        major: bool,
+
+        /// Accept packages without a verifiable manifest and signature (legacy specs)
+        #[arg(long)]
+        allow_unsigned: bool,
+    },
+
+    /// Show dependencies with newer versions available
+    Outdated {
+        /// Output format: table or json (default: table)
+        #[arg(long, value_name = "FORMAT")]
+        format: Option<String>,
+
+        /// Exit with an error if any dependency is outdated
+        #[arg(long)]
+        fail: bool,
+    },
+
+    /// Remove a dependency from canon.yml and prune packages nothing else needs
+    Remove {
+        /// Dependency to remove, as publisher/id or publisher/id@version
+        uri: String,
+    },
+
+    /// Show the resolved dependency graph
+    Tree {
+        /// Only show this many levels of the tree
+        #[arg(long)]
+        depth: Option<usize>,
+
+        /// Show what requires each package instead of what it requires
+        #[arg(long)]
+        invert: bool,
+
+        /// Output format: text, dot or json (default: text)
+        #[arg(long, value_name = "FORMAT")]
+        format: Option<String>,
+    },
+
+    /// Show every path from the project to a dependency
+    Why {
+        /// Dependency to explain, as publisher/id or publisher/id@version
+        uri: String,
+    },
+
     /// Validate specification syntax and structure
     Validate {
         /// Path to canon.yml or directory (default: current directory)
@@ -67,6 +143,22 @@ pub enum Commands {
         fix: bool,
     },
 
+    /// Re-check installed packages against their manifests and signatures
+    Verify {
+        /// Packages to verify, e.g. "profiles.org/author" (default: everything in .canon/)
+        uris: Vec<String>,
+
+        /// Accept packages without a verifiable manifest and signature (legacy specs)
+        #[arg(long)]
+        allow_unsigned: bool,
+    },
+
+    /// Generate canon-manifest.yml for a specification package
+    Manifest {
+        /// Package directory (default: current directory)
+        path: Option<String>,
+    },
+
     /// Generate canonical artifacts, manifest, and signature
     Build {
         /// Transformation engine (default: auto)
@@ -89,8 +181,8 @@ pub enum Commands {
         #[arg(long)]
         no_cache: bool,
 
-        /// Number of parallel transformations
-        #[arg(long)]
+        /// Number of templates to render at once (default: available CPUs)
+        #[arg(long, value_name = "N")]
         parallel: Option<usize>,
     },
 
@@ -129,6 +221,12 @@ pub enum Commands {
         #[command(subcommand)]
         command: ConfigCommands,
     },
+
+    /// Manage the package cache shared by all projects
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
 }
 
 #[derive(Subcommand)]
@@ -137,14 +235,50 @@ pub enum ConfigCommands {
     Get { key: String },
 
     /// Set configuration value
-    Set { key: String, value: String },
+    Set {
+        key: String,
+        value: String,
+
+        /// Write to the project's .canon.toml instead of the user config
+        #[arg(long)]
+        project: bool,
+    },
 
     /// Remove configuration value
-    Unset { key: String },
+    Unset {
+        key: String,
+
+        /// Remove from the project's .canon.toml instead of the user config
+        #[arg(long)]
+        project: bool,
+    },
 
-    /// List all configuration
+    /// List all configuration and where each value comes from
     List,
 
-    /// Edit configuration file
-    Edit,
+    /// Edit configuration file in $EDITOR
+    Edit {
+        /// Edit the project's .canon.toml instead of the user config
+        #[arg(long)]
+        project: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// List cached packages
+    List,
+
+    /// Remove packages not used recently
+    Prune {
+        /// Remove packages not used in this many days
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        older_than: u64,
+    },
+
+    /// Re-hash cached packages and remove any that are corrupt
+    Verify,
+
+    /// Remove the whole cache
+    Clean,
 }
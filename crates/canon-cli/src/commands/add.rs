@@ -1,10 +1,17 @@
+use crate::config::Config;
+use crate::core::http::HttpClient;
+use crate::core::package_cache::PackageCache;
+use crate::core::registry::{Registry, RegistryLocation};
+use crate::core::resolver::package_key;
 use crate::core::{CanonSpecification, Dependency};
 use crate::utils::{CanonError, CanonResult};
+use canon_protocol::semver::Version;
+use canon_protocol::VersionOperator;
 use console::style;
 use serde_yaml::Value;
 use std::fs;
 
-pub async fn run_add(uri: &str) -> CanonResult<()> {
+pub async fn run_add(config: &Config, uri: &str) -> CanonResult<()> {
     let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
         message: format!("Failed to get current directory: {}", e),
     })?;
@@ -19,7 +26,7 @@ pub async fn run_add(uri: &str) -> CanonResult<()> {
     }
 
     // Validate the dependency URI format
-    Dependency::parse(uri).map_err(CanonError::Protocol)?;
+    let dep = Dependency::parse(uri).map_err(CanonError::Protocol)?;
 
     // Read and parse canon.yml
     let yaml_content = fs::read_to_string(&canon_yml_path).map_err(CanonError::Io)?;
@@ -41,19 +48,29 @@ pub async fn run_add(uri: &str) -> CanonResult<()> {
             message: "Dependencies field must be an array".to_string(),
         })?;
 
-    // Check if dependency already exists
-    let uri_value = Value::String(uri.to_string());
-    if deps_array.contains(&uri_value) {
+    // Without a version, any entry for the package counts as already there
+    let existing = deps_array.iter().filter_map(Value::as_str).find(|d| {
+        *d == uri
+            || (dep.version.is_none()
+                && Dependency::parse(d).is_ok_and(|d| package_key(&d) == package_key(&dep)))
+    });
+    if let Some(existing) = existing {
         println!(
             "{} {} already exists in canon.yml",
             style("Dependency").yellow(),
-            style(uri).cyan()
+            style(existing).cyan()
         );
         return Ok(());
     }
 
+    // A bare publisher/id becomes a caret range on the newest release
+    let uri = match dep.version {
+        Some(_) => uri.to_string(),
+        None => newest_release_range(config, &dep).await?.to_uri(),
+    };
+
     // Add the dependency
-    deps_array.push(uri_value);
+    deps_array.push(Value::String(uri.clone()));
 
     // Write updated canon.yml
     let yaml_content = serde_yaml::to_string(&spec).map_err(CanonError::Serialization)?;
@@ -62,7 +79,7 @@ pub async fn run_add(uri: &str) -> CanonResult<()> {
     println!(
         "{} {} to canon.yml",
         style("Added").green().bold(),
-        style(uri).cyan()
+        style(&uri).cyan()
     );
     println!();
     println!(
@@ -72,7 +89,45 @@ pub async fn run_add(uri: &str) -> CanonResult<()> {
     println!();
 
     // Run install to fetch the new dependency
-    crate::commands::install::run_install().await?;
+    crate::commands::install::run_install(config, false, false, false, None).await?;
 
     Ok(())
 }
+
+/// `^x.y.z` on the newest release of `dep`'s package
+///
+/// The registry's version listing is used, or offline the package cache.
+async fn newest_release_range(config: &Config, dep: &Dependency) -> CanonResult<Dependency> {
+    let location = RegistryLocation::from_config(config)?;
+    let versions = if config.offline() {
+        PackageCache::from_config(config)
+            .map(|cache| cache.versions(&location, dep))
+            .unwrap_or_default()
+    } else {
+        let http = HttpClient::from_config(config)?;
+        location
+            .connect(&http)?
+            .list_versions(&dep.publisher, &dep.id)
+            .await?
+            .iter()
+            .filter_map(|v| Version::parse(v).ok())
+            .collect()
+    };
+
+    let newest = versions
+        .into_iter()
+        .filter(|v| v.pre.is_empty())
+        .max()
+        .ok_or_else(|| CanonError::Command {
+            message: format!(
+                "No released version of {} found on {}",
+                package_key(dep),
+                location.url()
+            ),
+        })?;
+    Ok(Dependency {
+        version: Some(newest.to_string()),
+        version_operator: Some(VersionOperator::Caret),
+        ..dep.clone()
+    })
+}
@@ -1,13 +1,548 @@
-use crate::utils::CanonResult;
+use crate::config::Config;
+use crate::core::build_cache::{context_hash, BuildCache};
+use crate::core::canon_dir::{replace_dir, CanonDir};
+use crate::core::integrity::is_safe_path;
+use crate::core::manifest::{generate_manifest, write_manifest};
+use crate::core::registry::RegistryLocation;
+use crate::core::transform::{build_context, plan_templates, render, Engine, Template};
+use crate::core::{CanonSpecification, Dependency};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::{
+    parse_version, signing_key_from_base64, CanonLock, CanonManifest, CanonSignature,
+};
+use console::style;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+/// Private signing key file passed with `--key`
+#[derive(Debug, Deserialize)]
+struct SigningKeyFile {
+    /// Key id as listed in the publisher's keys document
+    key_id: String,
+    /// Base64-encoded 32-byte Ed25519 seed
+    private_key: String,
+}
+
+/// Settings for building the canonical package of the current spec
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// Transformation engine override (`handlebars`, `tera`, or `auto`)
+    pub engine: Option<String>,
+    /// Directory packages are built into (default: .canon)
+    pub output: PathBuf,
+    pub sign: bool,
+    pub key: Option<PathBuf>,
+    /// Render every template even if a cached output exists
+    pub no_cache: bool,
+    /// Templates rendered at once (default: available CPUs)
+    pub parallel: Option<usize>,
+    /// Registry whose installed packages provide dependency specs without a lock
+    pub registry: RegistryLocation,
+}
+
+/// A built canonical package
+#[derive(Debug)]
+pub struct BuiltPackage {
+    pub path: PathBuf,
+    pub artifacts: Vec<BuiltArtifact>,
+    pub manifest: CanonManifest,
+    pub signature: Option<CanonSignature>,
+}
+
+/// An artifact rendered into the package
+#[derive(Debug)]
+pub struct BuiltArtifact {
+    /// Path relative to the package directory
+    pub path: String,
+    /// Reused from the build cache rather than rendered
+    pub cached: bool,
+}
 
 pub async fn run_build(
-    _engine: Option<String>,
-    _output: Option<String>,
-    _sign: bool,
-    _key: Option<String>,
-    _no_cache: bool,
-    _parallel: Option<usize>,
+    config: &Config,
+    engine: Option<String>,
+    output: Option<String>,
+    sign: bool,
+    key: Option<String>,
+    no_cache: bool,
+    parallel: Option<usize>,
 ) -> CanonResult<()> {
-    println!("Build command not yet implemented");
+    if parallel == Some(0) {
+        return Err(CanonError::Config {
+            message: "--parallel must be at least 1".to_string(),
+        });
+    }
+
+    let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
+        message: format!("Failed to get current directory: {}", e),
+    })?;
+
+    let options = BuildOptions {
+        engine,
+        output: output
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(".canon")),
+        sign,
+        key: key.map(PathBuf::from).or_else(|| config.signing_key()),
+        no_cache,
+        parallel,
+        registry: RegistryLocation::from_config(config)?,
+    };
+
+    println!("{} canonical package", style("Building").cyan().bold());
+    println!();
+
+    let package = build_package(&current_dir, &options).await?;
+
+    println!("  {} canon.yml (normalized)", style("✓").green());
+    for artifact in &package.artifacts {
+        if artifact.cached {
+            println!(
+                "  {} {} {}",
+                style("✓").green(),
+                artifact.path,
+                style("(cached)").dim()
+            );
+        } else {
+            println!("  {} {}", style("✓").green(), artifact.path);
+        }
+    }
+    println!(
+        "  {} {} ({} files)",
+        style("✓").green(),
+        CanonManifest::FILE_NAME,
+        package.manifest.file_count
+    );
+    if let Some(signature) = &package.signature {
+        println!(
+            "  {} {} (key {})",
+            style("✓").green(),
+            CanonSignature::FILE_NAME,
+            signature.signature.key_id
+        );
+    }
+    println!();
+    println!(
+        "{} {}",
+        style("✓ Built").green().bold(),
+        package.path.display()
+    );
+    println!(
+        "  Canonical hash: {}",
+        style(&package.manifest.canonical_hash).dim()
+    );
+
     Ok(())
 }
+
+/// Build the canonical package for the spec in `project_dir`
+///
+/// The package lands in `<output>/localhost/<publisher>/<id>/<version>` and
+/// contains the normalized canon.yml, any artifacts rendered from the
+/// spec's templates, its manifest, and a signature when signing is
+/// requested. Any previous build of the same version is replaced in one
+/// step, with `.canon/` locked for the duration; an `--output` outside
+/// `.canon/` is staged next to the package instead, on its own filesystem.
+pub async fn build_package(
+    project_dir: &Path,
+    options: &BuildOptions,
+) -> CanonResult<BuiltPackage> {
+    let spec = read_spec(&project_dir.join("canon.yml"))?;
+
+    // These name the directory that is replaced below, so check them first
+    package_segment("publisher", &spec.metadata.publisher)?;
+    package_segment("id", &spec.metadata.id)?;
+    parse_version(&spec.metadata.version)?;
+
+    // The build cache and the package both live under .canon/
+    let canon_dir = CanonDir::lock(project_dir)?;
+
+    // Render everything up front so a template error leaves the last build intact
+    let engine = Engine::parse(options.engine.as_deref())?;
+    let templates = plan_templates(project_dir, &spec, engine)?;
+    let mut artifacts = Vec::new();
+    if !templates.is_empty() {
+        let dependencies = load_dependency_specs(project_dir, &spec, &options.registry)?;
+        let context = build_context(&spec, &dependencies)?;
+        let context_hash = context_hash(&spec, &dependencies)?;
+        let cache = BuildCache::new(&project_dir.join(".canon"), !options.no_cache);
+        let parallel = options.parallel.unwrap_or_else(default_parallelism);
+
+        artifacts =
+            render_artifacts(templates, context, context_hash, cache, parallel.max(1)).await?;
+    }
+
+    let package_dir = project_dir
+        .join(&options.output)
+        .join("localhost")
+        .join(&spec.metadata.publisher)
+        .join(&spec.metadata.id)
+        .join(&spec.metadata.version);
+
+    // Load the key before touching the output so a bad key leaves no partial build
+    let signing_key = if options.sign {
+        let key_path = options.key.as_ref().ok_or_else(|| CanonError::Command {
+            message: "--sign requires --key <path> to a signing key file".to_string(),
+        })?;
+        Some(read_signing_key(key_path)?)
+    } else {
+        None
+    };
+
+    let canonical = spec.to_canonical_yaml().map_err(CanonError::Protocol)?;
+    let mut built = None;
+    let fill = |staging: &Path| {
+        fs::create_dir_all(staging).map_err(CanonError::Io)?;
+        fs::write(staging.join("canon.yml"), &canonical).map_err(CanonError::Io)?;
+
+        for (artifact, content) in &artifacts {
+            let path = staging.join(&artifact.path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(CanonError::Io)?;
+            }
+            fs::write(path, content).map_err(CanonError::Io)?;
+        }
+
+        let manifest = generate_manifest(staging)?;
+        write_manifest(staging, &manifest)?;
+
+        let signature = match signing_key {
+            Some((key_id, key)) => {
+                let signature = CanonSignature::sign(&manifest, &key_id, &key);
+                let yaml = serde_yaml::to_string(&signature).map_err(CanonError::Serialization)?;
+                fs::write(staging.join(CanonSignature::FILE_NAME), yaml).map_err(CanonError::Io)?;
+                Some(signature)
+            }
+            None => None,
+        };
+        built = Some((manifest, signature));
+        Ok(())
+    };
+    // Staging in .canon/ could be on another filesystem than --output
+    if package_dir.starts_with(project_dir.join(".canon")) {
+        canon_dir.install(&package_dir, fill)?;
+    } else {
+        replace_dir(&package_dir, fill)?;
+    }
+    let (manifest, signature) = built.expect("install ran fill");
+
+    Ok(BuiltPackage {
+        path: package_dir,
+        artifacts: artifacts
+            .into_iter()
+            .map(|(artifact, _)| artifact)
+            .collect(),
+        manifest,
+        signature,
+    })
+}
+
+/// Render templates on blocking tasks, at most `parallel` at a time
+///
+/// Artifacts come back in template order whatever order they finish in. The
+/// first failure aborts every other task and is returned straight away;
+/// renders already running on a blocking thread cannot be interrupted and
+/// finish in the background, but their output is discarded.
+async fn render_artifacts(
+    templates: Vec<Template>,
+    context: Value,
+    context_hash: String,
+    cache: BuildCache,
+    parallel: usize,
+) -> CanonResult<Vec<(BuiltArtifact, String)>> {
+    let shared = Arc::new((context, context_hash, cache));
+    let mut results: Vec<Option<(BuiltArtifact, String)>> = Vec::new();
+    results.resize_with(templates.len(), || None);
+
+    let mut pending = templates.into_iter().enumerate();
+    let mut tasks = JoinSet::new();
+
+    loop {
+        while tasks.len() < parallel {
+            let Some((index, template)) = pending.next() else {
+                break;
+            };
+            let shared = Arc::clone(&shared);
+            tasks.spawn_blocking(move || {
+                let (context, context_hash, cache) = &*shared;
+                (
+                    index,
+                    render_artifact(&template, context, context_hash, cache),
+                )
+            });
+        }
+
+        let Some(joined) = tasks.join_next().await else {
+            break;
+        };
+        let error = match joined {
+            Ok((index, Ok(artifact))) => {
+                results[index] = Some(artifact);
+                continue;
+            }
+            Ok((_, Err(e))) => e,
+            Err(e) => CanonError::Command {
+                message: format!("Transformation task failed: {}", e),
+            },
+        };
+        tasks.abort_all();
+        return Err(error);
+    }
+
+    Ok(results.into_iter().flatten().collect())
+}
+
+/// Render one template, reusing the cached output when there is one
+fn render_artifact(
+    template: &Template,
+    context: &Value,
+    context_hash: &str,
+    cache: &BuildCache,
+) -> CanonResult<(BuiltArtifact, String)> {
+    let source = fs::read_to_string(&template.source).map_err(CanonError::Io)?;
+    let key = BuildCache::key(template, &source, context_hash);
+    let (content, cached) = match cache.get(&key) {
+        Some(content) => (content, true),
+        None => {
+            let content = render(template, &source, context)?;
+            cache.put(&key, &content)?;
+            (content, false)
+        }
+    };
+
+    Ok((
+        BuiltArtifact {
+            path: template.artifact.clone(),
+            cached,
+        },
+        content,
+    ))
+}
+
+fn default_parallelism() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Reject a metadata field that would not name a single directory
+fn package_segment(field: &str, value: &str) -> CanonResult<()> {
+    if is_safe_path(value) && !value.contains(['/', '\\']) {
+        return Ok(());
+    }
+    Err(CanonError::ValidationError {
+        message: format!(
+            "metadata.{} '{}' cannot be used as a package directory name",
+            field, value
+        ),
+    })
+}
+
+fn read_spec(path: &Path) -> CanonResult<CanonSpecification> {
+    if !path.exists() {
+        return Err(CanonError::Command {
+            message: "No canon.yml found in current directory".to_string(),
+        });
+    }
+
+    let content = fs::read_to_string(path).map_err(CanonError::Io)?;
+    serde_yaml::from_str(&content).map_err(|e| CanonError::Config {
+        message: format!("Failed to parse canon.yml: {}", e),
+    })
+}
+
+/// Installed specs of the project's resolved dependencies, for template context
+///
+/// Uses canon.lock when present, each package read from the registry it was
+/// locked from; otherwise the exactly-versioned entries in canon.yml, from
+/// `registry`.
+fn load_dependency_specs(
+    project_dir: &Path,
+    spec: &CanonSpecification,
+    registry: &RegistryLocation,
+) -> CanonResult<Vec<(Dependency, CanonSpecification)>> {
+    let lock =
+        CanonLock::load(&project_dir.join(CanonLock::FILE_NAME)).map_err(CanonError::Protocol)?;
+    let dependencies: Vec<(Dependency, RegistryLocation)> = match lock {
+        Some(lock) => lock
+            .packages
+            .iter()
+            .map(|package| {
+                Ok((
+                    package.to_dependency(),
+                    RegistryLocation::parse(&package.registry)?,
+                ))
+            })
+            .collect::<CanonResult<_>>()?,
+        None => spec
+            .dependencies()
+            .iter()
+            .filter_map(|uri| Dependency::parse(uri).ok())
+            .filter(|dep| dep.pinned_version().is_ok())
+            .map(|dep| (dep, registry.clone()))
+            .collect(),
+    };
+
+    dependencies
+        .into_iter()
+        .map(|(dep, registry)| {
+            let canon_yml = project_dir
+                .join(registry.local_path(&dep)?)
+                .join("canon.yml");
+            if !canon_yml.exists() {
+                return Err(CanonError::Command {
+                    message: format!(
+                        "Dependency {} is not installed. Run 'canon install' first.",
+                        dep.to_uri()
+                    ),
+                });
+            }
+            let dep_spec = read_spec(&canon_yml)?;
+            Ok((dep, dep_spec))
+        })
+        .collect()
+}
+
+fn read_signing_key(path: &Path) -> CanonResult<(String, ed25519_dalek::SigningKey)> {
+    let content = fs::read_to_string(path).map_err(|e| CanonError::Config {
+        message: format!("Failed to read signing key {}: {}", path.display(), e),
+    })?;
+    let key_file: SigningKeyFile =
+        serde_yaml::from_str(&content).map_err(|e| CanonError::Config {
+            message: format!("Invalid signing key file {}: {}", path.display(), e),
+        })?;
+    let key = signing_key_from_base64(&key_file.private_key).map_err(CanonError::Protocol)?;
+    Ok((key_file.key_id, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn templates(dir: &Path, sources: &[&str]) -> Vec<Template> {
+        sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                let path = dir.join(format!("t{}.hbs", i));
+                fs::write(&path, source).unwrap();
+                Template {
+                    source: path,
+                    artifact: format!("artifacts/t{}", i),
+                    engine: Engine::Handlebars,
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_render_artifacts_keeps_template_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources: Vec<String> = (0..8).map(|i| format!("{} {{{{name}}}}", i)).collect();
+        let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+        let cache = BuildCache::new(dir.path(), false);
+
+        let artifacts = render_artifacts(
+            templates(dir.path(), &sources),
+            json!({"name": "x"}),
+            String::new(),
+            cache,
+            3,
+        )
+        .await
+        .unwrap();
+
+        let paths: Vec<&str> = artifacts.iter().map(|(a, _)| a.path.as_str()).collect();
+        let expected: Vec<String> = (0..8).map(|i| format!("artifacts/t{}", i)).collect();
+        assert_eq!(paths, expected);
+        assert_eq!(artifacts[5].1, "5 x");
+    }
+
+    #[tokio::test]
+    async fn test_render_artifacts_reports_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BuildCache::new(dir.path(), false);
+
+        let result = render_artifacts(
+            templates(dir.path(), &["ok", "{{#if}}", "ok"]),
+            json!({}),
+            String::new(),
+            cache,
+            2,
+        )
+        .await;
+
+        match result {
+            Err(CanonError::TransformationError { transformation, .. }) => {
+                assert!(transformation.ends_with("t1.hbs"))
+            }
+            other => panic!("expected transformation error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_rejects_unsafe_package_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let keep = dir.path().join(".canon/localhost/keep");
+        fs::create_dir_all(&keep).unwrap();
+        let options = BuildOptions {
+            engine: None,
+            output: PathBuf::from(".canon"),
+            sign: false,
+            key: None,
+            no_cache: true,
+            parallel: None,
+            registry: RegistryLocation::parse("https://example.com").unwrap(),
+        };
+
+        for (publisher, id, version) in [
+            ("..", "keep", ".."),
+            ("acme.com", "a/b", "1.0.0"),
+            ("acme.com", "api", ".."),
+            ("acme.com", "api", "1.0"),
+        ] {
+            let spec = format!(
+                "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: \"{}\"\n  version: \"{}\"\n  publisher: \"{}\"\n",
+                id, version, publisher
+            );
+            fs::write(dir.path().join("canon.yml"), spec).unwrap();
+            assert!(build_package(dir.path(), &options).await.is_err());
+            assert!(keep.exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_outside_project_stages_next_to_output() {
+        let project = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        fs::write(
+            project.path().join("canon.yml"),
+            "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: 1.0.0\n  publisher: acme.com\n",
+        )
+        .unwrap();
+        let options = BuildOptions {
+            engine: None,
+            output: output.path().to_path_buf(),
+            sign: false,
+            key: None,
+            no_cache: true,
+            parallel: None,
+            registry: RegistryLocation::parse("https://example.com").unwrap(),
+        };
+
+        // Built twice, so the second build replaces the first
+        for _ in 0..2 {
+            let built = build_package(project.path(), &options).await.unwrap();
+            assert!(built.path.starts_with(output.path()));
+            assert!(built.path.join("canon.yml").exists());
+        }
+        let id_dir = output.path().join("localhost/acme.com/api");
+        assert!(!id_dir.join(".staging").exists());
+        assert!(!project.path().join(".canon/.staging").exists());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::config::Config;
+use crate::core::package_cache::PackageCache;
+use crate::utils::{CanonError, CanonResult};
+use chrono::{Duration, Utc};
+use console::style;
+use indicatif::HumanBytes;
+use std::fs;
+
+pub async fn list_cache(config: &Config) -> CanonResult<()> {
+    let cache = open(config)?;
+    let entries = cache.list()?;
+    if entries.is_empty() {
+        println!("Package cache is empty ({})", cache.root().display());
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for entry in &entries {
+        let size = entry.size();
+        total += size;
+        println!(
+            "  {} {}  {}  {}",
+            entry.uri(),
+            style(format!("({})", entry.registry)).dim(),
+            &entry.hash[..12.min(entry.hash.len())],
+            style(format!(
+                "{}, last used {}",
+                HumanBytes(size),
+                entry.info.last_used.format("%Y-%m-%d")
+            ))
+            .dim()
+        );
+    }
+
+    println!();
+    println!(
+        "{} packages, {} in {}",
+        entries.len(),
+        HumanBytes(total),
+        cache.root().display()
+    );
+    Ok(())
+}
+
+/// Remove entries not used within `older_than_days`, plus interrupted writes
+pub async fn prune_cache(config: &Config, older_than_days: u64) -> CanonResult<()> {
+    let cache = open(config)?;
+    let cutoff = Utc::now() - Duration::days(older_than_days.try_into().unwrap_or(i64::MAX));
+
+    let mut removed = 0;
+    let mut freed = 0;
+    for entry in cache.list()? {
+        if entry.info.last_used < cutoff {
+            freed += entry.size();
+            entry.remove()?;
+            println!("  {} {}", style("✓").green(), entry.uri());
+            removed += 1;
+        }
+    }
+    cache.remove_staging()?;
+
+    println!(
+        "{} {} packages not used in {} days ({})",
+        style("Pruned").green().bold(),
+        removed,
+        older_than_days,
+        HumanBytes(freed)
+    );
+    Ok(())
+}
+
+/// Re-hash every entry; corrupt ones are removed so the next install refetches them
+pub async fn verify_cache(config: &Config) -> CanonResult<()> {
+    let cache = open(config)?;
+    let entries = cache.list()?;
+    println!(
+        "{} {} cached packages",
+        style("Verifying").cyan().bold(),
+        entries.len()
+    );
+    println!();
+
+    let mut corrupt = 0;
+    for entry in &entries {
+        match entry.check() {
+            Ok(()) => println!("  {} {}", style("✓").green(), entry.uri()),
+            Err(e) => {
+                eprintln!("  {} {} - {} (removed)", style("✗").red(), entry.uri(), e);
+                entry.remove()?;
+                corrupt += 1;
+            }
+        }
+    }
+
+    println!();
+    if corrupt > 0 {
+        Err(CanonError::ValidationError {
+            message: format!(
+                "{} cached packages were corrupt and have been removed",
+                corrupt
+            ),
+        })
+    } else {
+        println!("{} All cached packages verified", style("✓").green().bold());
+        Ok(())
+    }
+}
+
+pub async fn clean_cache(config: &Config) -> CanonResult<()> {
+    let cache = open(config)?;
+    if !cache.root().exists() {
+        println!("Package cache is already empty");
+        return Ok(());
+    }
+
+    fs::remove_dir_all(cache.root()).map_err(CanonError::Io)?;
+    println!(
+        "{} {}",
+        style("Removed").green().bold(),
+        cache.root().display()
+    );
+    Ok(())
+}
+
+fn open(config: &Config) -> CanonResult<PackageCache> {
+    PackageCache::from_config(config).ok_or_else(|| CanonError::Config {
+        message: "Cannot locate a cache directory; set cache.dir".to_string(),
+    })
+}
@@ -1,3 +1,4 @@
+use crate::core::canon_dir::CanonDir;
 use crate::utils::{CanonError, CanonResult};
 use console::style;
 use std::fs;
@@ -21,7 +22,7 @@ pub async fn run_clean(all: bool, purge: bool) -> CanonResult<()> {
         let mut removed_items = Vec::new();
 
         if canon_dir.exists() {
-            fs::remove_dir_all(canon_dir).map_err(CanonError::Io)?;
+            CanonDir::lock(Path::new("."))?.remove()?;
             removed_items.push(".canon/");
         }
 
@@ -30,6 +31,12 @@ pub async fn run_clean(all: bool, purge: bool) -> CanonResult<()> {
             removed_items.push("canon.yml");
         }
 
+        let canon_lock = Path::new(canon_protocol::CanonLock::FILE_NAME);
+        if canon_lock.exists() {
+            fs::remove_file(canon_lock).map_err(CanonError::Io)?;
+            removed_items.push(canon_protocol::CanonLock::FILE_NAME);
+        }
+
         // Also remove from .gitignore if present
         let gitignore = Path::new(".gitignore");
         if gitignore.exists() {
@@ -67,7 +74,7 @@ pub async fn run_clean(all: bool, purge: bool) -> CanonResult<()> {
         }
 
         if canon_dir.exists() {
-            fs::remove_dir_all(canon_dir).map_err(CanonError::Io)?;
+            CanonDir::lock(Path::new("."))?.remove()?;
             println!("  {} Removed .canon/", style("✓").green());
             println!();
             println!("All cached dependencies have been removed");
@@ -1,26 +1,183 @@
-use crate::utils::CanonResult;
+use crate::config::{
+    check_table, display_value, find_key, read_table, remove_value, set_value, user_only_error,
+    write_table, Config, Kind, KEYS,
+};
+use crate::utils::{CanonError, CanonResult};
+use console::style;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
-pub async fn get_config(_key: &str) -> CanonResult<()> {
-    println!("Config get command not yet implemented");
-    Ok(())
+pub async fn get_config(config: &Config, key: &str) -> CanonResult<()> {
+    let key = find_key(key)?;
+    match config.get(key.name) {
+        Some(setting) => {
+            println!("{}", display_value(&setting.value));
+            Ok(())
+        }
+        None => Err(CanonError::Config {
+            message: format!("{} is not set", key.name),
+        }),
+    }
 }
 
-pub async fn set_config(_key: &str, _value: &str) -> CanonResult<()> {
-    println!("Config set command not yet implemented");
+pub async fn set_config(config: &Config, key: &str, value: &str, project: bool) -> CanonResult<()> {
+    let key = find_key(key)?;
+    if project && key.user_only {
+        return Err(user_only_error(key));
+    }
+    let value = key.parse(value)?;
+    let path = target_file(config, project)?;
+
+    let mut table = read_table(&path)?;
+    set_value(&mut table, key.name, value.clone());
+    write_table(&path, &table)?;
+
+    println!(
+        "{} Set {} = {} in {}",
+        style("✓").green(),
+        key.name,
+        display_value(&value),
+        path.display()
+    );
     Ok(())
 }
 
-pub async fn unset_config(_key: &str) -> CanonResult<()> {
-    println!("Config unset command not yet implemented");
+pub async fn unset_config(config: &Config, key: &str, project: bool) -> CanonResult<()> {
+    let key = find_key(key)?;
+    let path = target_file(config, project)?;
+
+    let mut table = read_table(&path)?;
+    if !remove_value(&mut table, key.name) {
+        println!("{} is not set in {}", key.name, path.display());
+        return Ok(());
+    }
+    write_table(&path, &table)?;
+
+    println!(
+        "{} Removed {} from {}",
+        style("✓").green(),
+        key.name,
+        path.display()
+    );
     Ok(())
 }
 
-pub async fn list_config() -> CanonResult<()> {
-    println!("Config list command not yet implemented");
+pub async fn list_config(config: &Config) -> CanonResult<()> {
+    for key in KEYS {
+        match config.get(key.name) {
+            Some(setting) => println!(
+                "{} = {}  {}",
+                key.name,
+                display_value(&setting.value),
+                style(format!("({})", setting.source)).dim()
+            ),
+            None => println!("{} {}", key.name, style("(not set)").dim()),
+        }
+    }
     Ok(())
 }
 
-pub async fn edit_config() -> CanonResult<()> {
-    println!("Config edit command not yet implemented");
+/// Open the user (or project) config file in `$VISUAL` / `$EDITOR`
+pub async fn edit_config(config: &Config, project: bool) -> CanonResult<()> {
+    let path = target_file(config, project)?;
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(CanonError::Io)?;
+        }
+        fs::write(&path, template()).map_err(CanonError::Io)?;
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            if cfg!(windows) {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        });
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or_else(|| CanonError::Config {
+        message: "$EDITOR is empty".to_string(),
+    })?;
+
+    let status = Command::new(program)
+        .args(parts)
+        .arg(&path)
+        .status()
+        .map_err(|e| CanonError::Command {
+            message: format!("Failed to launch editor '{}': {}", editor, e),
+        })?;
+    if !status.success() {
+        return Err(CanonError::Command {
+            message: format!("Editor '{}' exited with {}", editor, status),
+        });
+    }
+
+    let table = read_table(&path)?;
+    check_table(&path, &table)?;
+    println!("{} Saved {}", style("✓").green(), path.display());
     Ok(())
 }
+
+/// File written by set/unset/edit: the project's `.canon.toml` or the user file
+fn target_file(config: &Config, project: bool) -> CanonResult<PathBuf> {
+    if project {
+        return Ok(config.project_file().to_path_buf());
+    }
+    config
+        .user_file()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| CanonError::Config {
+            message: "Could not locate the user config directory; pass --config <path>".to_string(),
+        })
+}
+
+/// Starting content for a new config file: every key, commented out
+///
+/// Uncommenting any set of lines leaves valid TOML.
+fn template() -> String {
+    let mut content = String::from("# Canon configuration\n");
+    let mut current = None;
+    for key in KEYS {
+        let (section, name) = key.name.split_once('.').unwrap_or(("", key.name));
+        if current != Some(section) {
+            content.push_str(&format!("\n# [{}]\n", section));
+            current = Some(section);
+        }
+        content.push_str(&format!("# {}\n", key.description));
+        match key.default {
+            Some(default) if matches!(key.kind, Kind::Seconds | Kind::Count | Kind::Bool) => {
+                content.push_str(&format!("# {} = {}\n", name, default))
+            }
+            Some(default) => content.push_str(&format!("# {} = \"{}\"\n", name, default)),
+            None => content.push_str(&format!("# {} = \"\"\n", name)),
+        }
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml::Table;
+
+    #[test]
+    fn test_template_defaults_are_valid() {
+        // Uncomment every key that has a default, and the section headers
+        let template = template();
+        let uncommented: Vec<&str> = template
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| {
+                line.starts_with('[') || (line.contains(" = ") && !line.ends_with("\"\""))
+            })
+            .collect();
+        let table: Table = uncommented.join("\n").parse().unwrap();
+
+        check_table(Path::new("template"), &table).unwrap();
+        assert_eq!(table["network"]["retries"].as_integer(), Some(3));
+        assert_eq!(table["network"]["offline"].as_bool(), Some(false));
+    }
+}
@@ -1,14 +1,20 @@
+use crate::config::Config;
+use crate::core::bundled::bundled_spec;
+use crate::core::canon_dir::CanonDir;
+use crate::core::http::HttpClient;
+use crate::core::registry::{Registry, RegistryLocation};
 use crate::utils::{CanonError, CanonResult};
-use canon_protocol::{CanonSpecification, Dependency, SpecificationMetadata};
+use canon_protocol::{parse_version, CanonSpecification, Dependency, SpecificationMetadata};
 use console::style;
 use dialoguer::{theme::ColorfulTheme, Input};
 use indicatif::{ProgressBar, ProgressStyle};
-use reqwest;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-pub async fn run_init(force: bool, non_interactive: bool) -> CanonResult<()> {
+pub async fn run_init(config: &Config, force: bool, non_interactive: bool) -> CanonResult<()> {
+    let location = RegistryLocation::from_config(config)?;
+    let registry = location.connect(&HttpClient::from_config(config)?)?;
     let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
         message: format!("Failed to get current directory: {}", e),
     })?;
@@ -34,6 +40,9 @@ pub async fn run_init(force: bool, non_interactive: bool) -> CanonResult<()> {
 
     println!();
 
+    // Creates .canon/ and keeps other canon processes out of it until done
+    let canon_dir = CanonDir::lock(&current_dir)?;
+
     // Create progress bar
     let pb = ProgressBar::new(3);
     pb.set_style(
@@ -43,19 +52,21 @@ pub async fn run_init(force: bool, non_interactive: bool) -> CanonResult<()> {
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "),
     );
 
-    // Download the project type specification
-    pb.set_message("Fetching canon-protocol.org/project@1.0.0");
-    let project_dep =
-        Dependency::parse("canon-protocol.org/project@1.0.0").map_err(CanonError::Protocol)?;
-    download_specification(&project_dep, &current_dir).await?;
-    pb.inc(1);
-
-    // Download the type meta-type specification
-    pb.set_message("Fetching canon-protocol.org/type@1.0.0");
-    let type_dep =
-        Dependency::parse("canon-protocol.org/type@1.0.0").map_err(CanonError::Protocol)?;
-    download_specification(&type_dep, &current_dir).await?;
-    pb.inc(1);
+    // The project type and the type meta-type; offline, the copies bundled with canon
+    for uri in [
+        "canon-protocol.org/project@1.0.0",
+        "canon-protocol.org/type@1.0.0",
+    ] {
+        let dep = Dependency::parse(uri).map_err(CanonError::Protocol)?;
+        if config.offline() {
+            pb.set_message(format!("Writing bundled {}", uri));
+            write_bundled_specification(&dep, &canon_dir, &location)?;
+        } else {
+            pb.set_message(format!("Fetching {}", uri));
+            download_specification(&dep, &canon_dir, &location, &registry).await?;
+        }
+        pb.inc(1);
+    }
 
     // Create the project specification using Canon Protocol format
     pb.set_message("Creating canon.yml");
@@ -71,12 +82,6 @@ pub async fn run_init(force: bool, non_interactive: bool) -> CanonResult<()> {
     let yaml_content = serde_yaml::to_string(&project_spec).map_err(CanonError::Serialization)?;
     fs::write(&canon_yml_path, yaml_content).map_err(CanonError::Io)?;
 
-    // Create .canon directory
-    let canon_dir = current_dir.join(".canon");
-    if !canon_dir.exists() {
-        fs::create_dir(&canon_dir).map_err(CanonError::Io)?;
-    }
-
     // Create or update .gitignore
     add_to_gitignore(&current_dir)?;
 
@@ -90,7 +95,14 @@ pub async fn run_init(force: bool, non_interactive: bool) -> CanonResult<()> {
     println!("  • .canon/");
     println!("  • .gitignore (updated with .canon/)");
     println!();
-    println!("Downloaded:");
+    println!(
+        "{}",
+        if config.offline() {
+            "Bundled:"
+        } else {
+            "Downloaded:"
+        }
+    );
     println!("  • canon-protocol.org/project@1.0.0");
     println!("  • canon-protocol.org/type@1.0.0");
     println!();
@@ -170,43 +182,50 @@ fn format_title(id: &str) -> String {
         .join(" ")
 }
 
-async fn download_specification(dep: &Dependency, base_dir: &Path) -> CanonResult<()> {
-    // Create local directory for the specification
-    let local_path = base_dir.join(dep.local_path());
-    fs::create_dir_all(&local_path).map_err(CanonError::Io)?;
-
+async fn download_specification(
+    dep: &Dependency,
+    canon_dir: &CanonDir,
+    location: &RegistryLocation,
+    registry: &impl Registry,
+) -> CanonResult<()> {
     // Download the canon.yml file
-    let url = dep.canon_url();
-    let client = reqwest::Client::builder()
-        .user_agent("canon-cli/0.2.8")
-        .build()
-        .map_err(|e| CanonError::Network {
-            message: format!("Failed to create HTTP client: {}", e),
+    let content = registry
+        .fetch(dep, "canon.yml")
+        .await?
+        .ok_or(CanonError::RegistryError {
+            url: location.package_url(dep)?,
+            status: "not found".to_string(),
         })?;
 
-    let response = client
-        .get(&url)
-        .send()
-        .await
-        .map_err(|e| CanonError::Network {
-            message: format!("Failed to fetch {}: {}", url, e),
-        })?;
-
-    if !response.status().is_success() {
-        return Err(CanonError::Network {
-            message: format!("Failed to fetch {} (status: {})", url, response.status()),
-        });
-    }
+    // Save the specification
+    write_specification(dep, canon_dir, location, &content)
+}
 
-    let content = response.text().await.map_err(|e| CanonError::Network {
-        message: format!("Failed to read response: {}", e),
+/// Install the copy of a core specification shipped with canon
+fn write_bundled_specification(
+    dep: &Dependency,
+    canon_dir: &CanonDir,
+    location: &RegistryLocation,
+) -> CanonResult<()> {
+    let content = bundled_spec(dep).ok_or_else(|| CanonError::Command {
+        message: format!("{} is not bundled with canon", dep.to_uri()),
     })?;
 
-    // Save the specification
-    let spec_file = local_path.join("canon.yml");
-    fs::write(&spec_file, content).map_err(CanonError::Io)?;
+    write_specification(dep, canon_dir, location, content.as_bytes())
+}
 
-    Ok(())
+/// Install a lone canon.yml as the package for `dep`
+fn write_specification(
+    dep: &Dependency,
+    canon_dir: &CanonDir,
+    location: &RegistryLocation,
+    content: &[u8],
+) -> CanonResult<()> {
+    let local_path = canon_dir.project_dir().join(location.local_path(dep)?);
+    canon_dir.install(&local_path, |staging| {
+        fs::create_dir_all(staging).map_err(CanonError::Io)?;
+        fs::write(staging.join("canon.yml"), content).map_err(CanonError::Io)
+    })
 }
 
 fn get_default_values(current_dir: &Path) -> (String, String, String, String, String) {
@@ -296,11 +315,9 @@ fn get_interactive_values(
         .with_prompt("Initial version")
         .default("0.1.0".to_string())
         .validate_with(|input: &String| -> Result<(), &str> {
-            let parts: Vec<&str> = input.split('.').collect();
-            if parts.len() == 3 && parts.iter().all(|p| p.parse::<u32>().is_ok()) {
-                Ok(())
-            } else {
-                Err("Version must be semantic version (MAJOR.MINOR.PATCH)")
+            match parse_version(input) {
+                Ok(_) => Ok(()),
+                Err(_) => Err("Version must be a semantic version, e.g. 1.0.0 or 1.0.0-beta.1"),
             }
         })
         .interact()
@@ -1,11 +1,43 @@
+use crate::config::Config;
+use crate::core::bundled::bundled_spec;
+use crate::core::canon_dir::CanonDir;
+use crate::core::http::HttpClient;
+use crate::core::integrity::{
+    check_coordinates, check_manifest, verify_package, PublisherKeyring, Verification,
+};
+use crate::core::package_cache::{CacheEntry, PackageCache};
+use crate::core::registry::{DownloadedPackage, Registry, RegistryLocation};
+use crate::core::resolver::{Resolver, SpecSource};
+use crate::core::store::installed_versions;
 use crate::core::{CanonSpecification, Dependency};
 use crate::utils::{CanonError, CanonResult};
+use canon_protocol::semver::Version;
+use canon_protocol::{
+    is_complete_package, CanonLock, CanonManifest, CanonSignature, LockedPackage, ManifestFile,
+};
 use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Packages fetched at once when `--parallel` is not given
+const DEFAULT_PARALLEL: usize = 8;
+
+pub async fn run_install(
+    config: &Config,
+    locked: bool,
+    frozen: bool,
+    allow_unsigned: bool,
+    parallel: Option<usize>,
+) -> CanonResult<()> {
+    if parallel == Some(0) {
+        return Err(CanonError::Config {
+            message: "--parallel must be at least 1".to_string(),
+        });
+    }
 
-pub async fn run_install() -> CanonResult<()> {
     let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
         message: format!("Failed to get current directory: {}", e),
     })?;
@@ -19,6 +51,24 @@ pub async fn run_install() -> CanonResult<()> {
         });
     }
 
+    // Held until the install finishes, covering .canon/ and canon.lock
+    let canon_dir = CanonDir::lock(&current_dir)?;
+    install_project(config, &canon_dir, locked, frozen, allow_unsigned, parallel).await
+}
+
+/// Install canon.yml's dependencies into a project whose `.canon/` is already locked
+pub async fn install_project(
+    config: &Config,
+    canon_dir: &CanonDir,
+    locked: bool,
+    frozen: bool,
+    allow_unsigned: bool,
+    parallel: Option<usize>,
+) -> CanonResult<()> {
+    let location = RegistryLocation::from_config(config)?;
+    let current_dir = canon_dir.project_dir();
+    let canon_yml_path = current_dir.join("canon.yml");
+
     // Read and parse canon.yml as a Canon specification
     let yaml_content = fs::read_to_string(&canon_yml_path).map_err(CanonError::Io)?;
     let spec: CanonSpecification =
@@ -27,29 +77,46 @@ pub async fn run_install() -> CanonResult<()> {
         })?;
 
     // Extract dependencies from the content field
-    let dependencies = spec
-        .content
-        .get("dependencies")
-        .and_then(|v| v.as_sequence())
-        .map(|seq| {
-            seq.iter()
-                .filter_map(|v| v.as_str())
-                .map(String::from)
-                .collect::<Vec<_>>()
-        })
-        .unwrap_or_default();
+    let dependencies = spec.dependencies();
+
+    // Load the existing lockfile; --locked/--frozen require it to match canon.yml
+    let lock_path = current_dir.join(CanonLock::FILE_NAME);
+    let existing_lock = CanonLock::load(&lock_path).map_err(CanonError::Protocol)?;
+
+    if locked || frozen {
+        match &existing_lock {
+            None => {
+                return Err(CanonError::Command {
+                    message: format!(
+                        "{} is missing. Run 'canon install' without --locked/--frozen to create it.",
+                        CanonLock::FILE_NAME
+                    ),
+                });
+            }
+            Some(lock) if !lock.is_in_sync(&dependencies) => {
+                return Err(CanonError::Command {
+                    message: format!(
+                        "{} is out of date with canon.yml. Run 'canon install' without --locked/--frozen to update it.",
+                        CanonLock::FILE_NAME
+                    ),
+                });
+            }
+            Some(_) => {}
+        }
+    }
 
     if dependencies.is_empty() {
+        if !locked && !frozen {
+            write_lock(
+                &lock_path,
+                existing_lock.as_ref(),
+                CanonLock::new(&[], Vec::new()),
+            )?;
+        }
         println!("{} No dependencies to install", style("✓").green().bold());
         return Ok(());
     }
 
-    // Create .canon directory if it doesn't exist
-    let canon_dir = current_dir.join(".canon");
-    if !canon_dir.exists() {
-        fs::create_dir(&canon_dir).map_err(CanonError::Io)?;
-    }
-
     println!(
         "{} {} dependencies",
         style("Installing").green().bold(),
@@ -57,137 +124,700 @@ pub async fn run_install() -> CanonResult<()> {
     );
     println!();
 
-    let total_deps = dependencies.len();
-    let mut installed = 0;
-    let mut skipped = 0;
     let mut failed = 0;
-
+    let mut roots = Vec::new();
     for dep_uri in &dependencies {
-        // Parse dependency
-        let dep = match Dependency::parse(dep_uri) {
-            Ok(d) => d,
+        match Dependency::parse(dep_uri) {
+            Ok(dep) => roots.push(dep),
             Err(e) => {
                 eprintln!("  {} {} - {}", style("✗").red(), dep_uri, e);
                 failed += 1;
-                continue;
             }
-        };
+        }
+    }
+
+    // Resolve the full closure through includes and refs, installing as we go
+    let http = HttpClient::from_config(config)?;
+    let registry = location.connect(&http)?;
+    let source = InstallSource {
+        canon_dir,
+        location: &location,
+        registry: &registry,
+        lock: existing_lock.as_ref(),
+        cache: PackageCache::from_config(config),
+        frozen,
+        offline: config.offline(),
+        allow_unsigned,
+        keyring: PublisherKeyring::new(http),
+        progress: MultiProgress::new(),
+        outcomes: Mutex::default(),
+    };
+    let resolution = Resolver::new(existing_lock.as_ref())
+        .parallel(parallel.unwrap_or(DEFAULT_PARALLEL))
+        .resolve(&roots, &source)
+        .await?;
+    let outcomes = source.outcomes.into_inner().unwrap();
 
-        // Check if already installed
-        if dep.is_installed() {
-            println!("  {} {} (already installed)", style("✓").green(), dep_uri);
-            skipped += 1;
+    let mut locked_packages = Vec::new();
+    let (direct, transitive): (Vec<_>, Vec<_>) =
+        resolution.packages.values().partition(|p| p.direct);
+
+    for package in direct.iter().chain(transitive.iter()) {
+        let dep = &package.dependency;
+        let uri = dep.to_uri();
+        if resolution
+            .failures
+            .iter()
+            .any(|(failed_uri, _)| *failed_uri == uri)
+        {
             continue;
         }
 
-        // Install dependency
-        match install_dependency(&dep, &current_dir).await {
-            Ok(_) => {
-                println!("  {} {}", style("✓").green(), dep_uri);
-                installed += 1;
+        let label = if package.direct {
+            uri.clone()
+        } else {
+            format!("{} (via {})", uri, package.required_by.join(", "))
+        };
+
+        // Record what was installed, refusing anything that differs from the lock
+        let locked_package = existing_lock
+            .as_ref()
+            .and_then(|l| l.find(dep))
+            .filter(|p| Some(&p.version) == dep.version.as_ref());
+        match lock_entry(dep, locked_package, current_dir, &location) {
+            Ok(entry) => {
+                locked_packages.push(entry);
+                let cached = if outcomes.cached.contains(&uri) {
+                    format!(" {}", style("(cached)").dim())
+                } else {
+                    String::new()
+                };
+                if outcomes.skipped.contains(&uri) {
+                    println!("  {} {} (already installed)", style("✓").green(), label);
+                } else if outcomes.unsigned.contains(&uri) {
+                    println!(
+                        "  {} {} {}{}",
+                        style("✓").green(),
+                        label,
+                        style("(unsigned)").yellow(),
+                        cached
+                    );
+                } else {
+                    println!("  {} {}{}", style("✓").green(), label, cached);
+                }
             }
             Err(e) => {
-                eprintln!("  {} {} - {}", style("✗").red(), dep_uri, e);
+                eprintln!("  {} {} - {}", style("✗").red(), label, e);
                 failed += 1;
             }
         }
     }
 
+    for (uri, e) in &resolution.failures {
+        eprintln!("  {} {} - {}", style("✗").red(), uri, e);
+        failed += 1;
+    }
+
+    for cycle in &resolution.cycles {
+        println!(
+            "  {} Dependency cycle: {}",
+            style("⚠").yellow(),
+            cycle.join(" → ")
+        );
+    }
+
     println!();
     println!("{}", style("Summary:").bold());
-    println!("  • {} dependencies total", total_deps);
-    if installed > 0 {
-        println!("  • {} installed", style(installed).green());
+    println!(
+        "  • {} dependencies total ({} transitive)",
+        resolution.packages.len(),
+        transitive.len()
+    );
+    if !outcomes.installed.is_empty() {
+        if outcomes.cached.is_empty() {
+            println!("  • {} installed", style(outcomes.installed.len()).green());
+        } else {
+            println!(
+                "  • {} installed ({} from cache)",
+                style(outcomes.installed.len()).green(),
+                outcomes.cached.len()
+            );
+        }
     }
-    if skipped > 0 {
-        println!("  • {} already installed", style(skipped).yellow());
+    if !outcomes.skipped.is_empty() {
+        println!(
+            "  • {} already installed",
+            style(outcomes.skipped.len()).yellow()
+        );
     }
     if failed > 0 {
         println!("  • {} failed", style(failed).red());
     }
 
+    let mut missing = outcomes.missing;
+    if !missing.is_empty() {
+        missing.sort();
+        return Err(CanonError::Command {
+            message: format!(
+                "{} packages are not available offline:\n{}\nRun 'canon install' once with network access to fetch them into the package cache, then retry with --offline.",
+                missing.len(),
+                missing
+                    .iter()
+                    .map(|uri| format!("  - {}", uri))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        });
+    }
+
     if failed > 0 {
-        Err(CanonError::Command {
+        return Err(CanonError::Command {
             message: format!("{} dependencies failed to install", failed),
+        });
+    }
+
+    if !locked && !frozen {
+        let new_lock = CanonLock::new(&dependencies, locked_packages);
+        write_lock(&lock_path, existing_lock.as_ref(), new_lock)?;
+    }
+
+    Ok(())
+}
+
+/// Installs each package the resolver selects into `.canon/`
+///
+/// The resolver loads several packages at once; each fetch shows its own
+/// spinner line until it finishes.
+struct InstallSource<'a, R> {
+    canon_dir: &'a CanonDir,
+    location: &'a RegistryLocation,
+    registry: &'a R,
+    lock: Option<&'a CanonLock>,
+    cache: Option<PackageCache>,
+    frozen: bool,
+    offline: bool,
+    allow_unsigned: bool,
+    keyring: PublisherKeyring,
+    progress: MultiProgress,
+    outcomes: Mutex<Outcomes>,
+}
+
+/// URIs of packages by what installing them did
+#[derive(Default)]
+struct Outcomes {
+    installed: Vec<String>,
+    unsigned: Vec<String>,
+    skipped: Vec<String>,
+    /// Installed from the user-level package cache
+    cached: Vec<String>,
+    /// Not installed and not fetched because of --offline
+    missing: Vec<String>,
+}
+
+impl<R> InstallSource<'_, R> {
+    /// A verified copy of `dep` in the user cache, matching the hash in
+    /// canon.lock when there is one; cache errors count as a miss
+    ///
+    /// A signed entry is re-checked against the publisher's current keys, so
+    /// one signed with a key revoked since is fetched again. Offline that is
+    /// not possible and the entry is used as it is.
+    async fn cached(&self, dep: &Dependency) -> Option<CacheEntry> {
+        let hash = self
+            .lock
+            .and_then(|lock| lock.find(dep))
+            .filter(|locked| Some(&locked.version) == dep.version.as_ref())
+            .and_then(|locked| locked.canonical_hash.as_deref());
+
+        let entry = self
+            .cache
+            .as_ref()?
+            .find(self.location, dep, hash)
+            .ok()
+            .flatten()?;
+        match entry.verification() {
+            Verification::Unsigned => self.allow_unsigned.then_some(entry),
+            Verification::Signed { .. } if self.offline => Some(entry),
+            Verification::Signed { .. } => {
+                let keys = self.keyring.get(&dep.publisher).await?;
+                entry.verify_signature(&keys).ok().map(|()| entry)
+            }
+        }
+    }
+}
+
+impl<R: Registry> SpecSource for InstallSource<'_, R> {
+    async fn load(&self, dep: &Dependency) -> CanonResult<CanonSpecification> {
+        let package_dir = self
+            .canon_dir
+            .project_dir()
+            .join(self.location.local_path(dep)?);
+        let canon_yml = package_dir.join("canon.yml");
+
+        // Anything short of a complete package is replaced below
+        if is_complete_package(&package_dir) {
+            self.outcomes.lock().unwrap().skipped.push(dep.to_uri());
+        } else if self.frozen {
+            return Err(CanonError::Command {
+                message: "not installed and --frozen forbids fetching".to_string(),
+            });
+        } else if let Some(entry) = self.cached(dep).await {
+            self.canon_dir
+                .install(&package_dir, |staging| entry.link_into(staging))?;
+            let mut outcomes = self.outcomes.lock().unwrap();
+            if entry.verification() == Verification::Unsigned {
+                outcomes.unsigned.push(dep.to_uri());
+            }
+            outcomes.cached.push(dep.to_uri());
+            outcomes.installed.push(dep.to_uri());
+        } else if self.offline {
+            // Core specs ship with canon; anything else must be installed or cached
+            let Some(content) = bundled_spec(dep) else {
+                self.outcomes.lock().unwrap().missing.push(dep.to_uri());
+                return Err(CanonError::Command {
+                    message: "not installed and --offline forbids fetching".to_string(),
+                });
+            };
+            self.canon_dir.install(&package_dir, |staging| {
+                fs::create_dir_all(staging).map_err(CanonError::Io)?;
+                fs::write(staging.join("canon.yml"), content).map_err(CanonError::Io)
+            })?;
+            self.outcomes.lock().unwrap().installed.push(dep.to_uri());
+        } else {
+            let pb = self.progress.add(ProgressBar::new_spinner());
+            pb.set_style(
+                ProgressStyle::default_spinner()
+                    .template("    {spinner:.green} Fetching {msg}")
+                    .unwrap(),
+            );
+            pb.set_message(dep.to_uri());
+            pb.enable_steady_tick(Duration::from_millis(100));
+
+            let result = install_dependency(
+                dep,
+                self.canon_dir,
+                self.location,
+                self.registry,
+                self.allow_unsigned,
+                &self.keyring,
+                self.cache.as_ref(),
+            )
+            .await;
+            pb.finish_and_clear();
+
+            let mut outcomes = self.outcomes.lock().unwrap();
+            if result? == Verification::Unsigned {
+                outcomes.unsigned.push(dep.to_uri());
+            }
+            outcomes.installed.push(dep.to_uri());
+        }
+
+        let content = fs::read_to_string(&canon_yml).map_err(CanonError::Io)?;
+        serde_yaml::from_str(&content).map_err(|e| CanonError::ValidationError {
+            message: format!("Failed to parse {}: {}", canon_yml.display(), e),
         })
-    } else {
-        Ok(())
     }
+
+    /// The registry's listing, or without network what is installed or cached
+    async fn versions(&self, dep: &Dependency) -> CanonResult<Vec<Version>> {
+        if !self.offline && !self.frozen {
+            let versions = self.registry.list_versions(&dep.publisher, &dep.id).await?;
+            return Ok(versions
+                .iter()
+                .filter_map(|v| Version::parse(v).ok())
+                .collect());
+        }
+
+        let mut versions = installed_versions(self.canon_dir.project_dir(), self.location, dep)?;
+        if let Some(cache) = &self.cache {
+            versions.extend(cache.versions(self.location, dep));
+        }
+        Ok(versions)
+    }
+}
+
+/// Build the lock entry for an installed dependency
+fn lock_entry(
+    dep: &Dependency,
+    locked: Option<&LockedPackage>,
+    base_dir: &Path,
+    location: &RegistryLocation,
+) -> CanonResult<LockedPackage> {
+    let canonical_hash = read_canonical_hash(&base_dir.join(location.local_path(dep)?))?;
+
+    if let Some(expected) = locked.and_then(|l| l.canonical_hash.as_ref()) {
+        if canonical_hash.as_ref() != Some(expected) {
+            return Err(CanonError::Command {
+                message: format!(
+                    "canonical hash mismatch (locked {}, installed {})",
+                    expected,
+                    canonical_hash.as_deref().unwrap_or("none")
+                ),
+            });
+        }
+    }
+
+    Ok(LockedPackage {
+        publisher: dep.publisher.clone(),
+        id: dep.id.clone(),
+        version: dep.version.clone().unwrap_or_default(),
+        registry: location.url().to_string(),
+        canonical_hash,
+    })
 }
 
-async fn install_dependency(dep: &Dependency, base_dir: &Path) -> CanonResult<()> {
-    let local_path = base_dir.join(dep.local_path());
+/// Read the canonical hash from an installed package's manifest, if it has one
+fn read_canonical_hash(package_dir: &Path) -> CanonResult<Option<String>> {
+    let manifest_path = package_dir.join(CanonManifest::FILE_NAME);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&manifest_path).map_err(CanonError::Io)?;
+    let manifest: CanonManifest =
+        serde_yaml::from_str(&content).map_err(|e| CanonError::ValidationError {
+            message: format!("Failed to parse {}: {}", manifest_path.display(), e),
+        })?;
+
+    Ok(Some(manifest.canonical_hash))
+}
 
-    // Create parent directories
-    if let Some(parent) = local_path.parent() {
-        fs::create_dir_all(parent).map_err(CanonError::Io)?;
+/// Write canon.lock if its contents changed
+pub fn write_lock(path: &Path, existing: Option<&CanonLock>, lock: CanonLock) -> CanonResult<()> {
+    if existing == Some(&lock) {
+        return Ok(());
     }
 
-    // Create progress bar
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(
-        ProgressStyle::default_spinner()
-            .template("    {spinner:.green} Fetching {msg}")
-            .unwrap(),
+    lock.save(path).map_err(CanonError::Protocol)?;
+    println!();
+    println!(
+        "{} {}",
+        style("Updated").green().bold(),
+        CanonLock::FILE_NAME
     );
-    pb.set_message(format!("{}/{}", dep.publisher, dep.id));
+    Ok(())
+}
 
-    // Fetch canon.yml from canon.canon-protocol.org
-    let canon_yml_url = dep.canon_url();
-    let canon_yml_content = fetch_file(&canon_yml_url).await?;
+async fn install_dependency<R: Registry>(
+    dep: &Dependency,
+    canon_dir: &CanonDir,
+    location: &RegistryLocation,
+    registry: &R,
+    allow_unsigned: bool,
+    keyring: &PublisherKeyring,
+    cache: Option<&PackageCache>,
+) -> CanonResult<Verification> {
+    // canon.yml, manifest, signature and every file the manifest lists
+    let package = registry.download(dep).await?;
 
-    // Optionally fetch manifest and signature (these might not exist for all specs)
-    let manifest_url = canon_yml_url.replace("/canon.yml", "/canon-manifest.yml");
-    let manifest_content = fetch_file(&manifest_url).await.ok();
+    let manifest: Option<CanonManifest> = package
+        .manifest
+        .as_deref()
+        .map(serde_yaml::from_slice)
+        .transpose()
+        .map_err(|e| CanonError::ValidationError {
+            message: format!("Failed to parse {}: {}", CanonManifest::FILE_NAME, e),
+        })?;
+    let signature: Option<CanonSignature> = package
+        .signature
+        .as_deref()
+        .map(serde_yaml::from_slice)
+        .transpose()
+        .map_err(|e| CanonError::ValidationError {
+            message: format!("Failed to parse {}: {}", CanonSignature::FILE_NAME, e),
+        })?;
+    if let Some(manifest) = &manifest {
+        check_manifest(manifest)?;
+    }
 
-    let signature_url = canon_yml_url.replace("/canon.yml", "/canon-signature.yml");
-    let signature_content = fetch_file(&signature_url).await.ok();
+    // Publisher keys are fetched once per publisher per install
+    let keys = match signature {
+        Some(_) => keyring.get(&dep.publisher).await,
+        None => None,
+    };
 
-    pb.finish_and_clear();
+    let hashed: Vec<ManifestFile> = package
+        .files
+        .iter()
+        .map(|(path, content)| ManifestFile::from_contents(path, content))
+        .collect();
 
-    // Create local directory and save files
-    fs::create_dir_all(&local_path).map_err(CanonError::Io)?;
+    let canon_yml = package
+        .files
+        .get("canon.yml")
+        .map_or(&[][..], Vec::as_slice);
+    let verified = verify_package(
+        &hashed,
+        manifest.as_ref(),
+        signature.as_ref(),
+        keys.as_ref(),
+        allow_unsigned,
+    )
+    .and_then(|verification| {
+        check_coordinates(dep, manifest.as_ref(), canon_yml)?;
+        Ok(verification)
+    });
+    let verification = match verified {
+        Ok(verification) => verification,
+        Err(e) => {
+            // Keep what was downloaded for inspection, outside the install tree
+            let quarantine = canon_dir
+                .project_dir()
+                .join(quarantine_path(location, dep)?);
+            canon_dir.install(&quarantine, |staging| write_package(staging, &package))?;
+            return Err(CanonError::ValidationError {
+                message: format!("{} (quarantined in {})", e, quarantine.display()),
+            });
+        }
+    };
 
-    let canon_yml_path = local_path.join("canon.yml");
-    fs::write(canon_yml_path, canon_yml_content).map_err(CanonError::Io)?;
+    // Staged and moved into place whole, so an interrupted install leaves nothing behind
+    let local_path = canon_dir.project_dir().join(location.local_path(dep)?);
+    canon_dir.install(&local_path, |staging| write_package(staging, &package))?;
 
-    if let Some(manifest) = manifest_content {
-        let manifest_path = local_path.join("canon-manifest.yml");
-        fs::write(manifest_path, manifest).map_err(CanonError::Io)?;
+    // The cache only saves later downloads, so failing to fill it is not an error
+    if let Some(cache) = cache {
+        let _ = cache.store(location, dep, &package, &verification);
     }
 
-    if let Some(signature) = signature_content {
-        let signature_path = local_path.join("canon-signature.yml");
-        fs::write(signature_path, signature).map_err(CanonError::Io)?;
+    Ok(verification)
+}
+
+/// Where a package that failed verification is kept
+fn quarantine_path(location: &RegistryLocation, dep: &Dependency) -> CanonResult<PathBuf> {
+    let local_path = location.local_path(dep)?;
+    let mut path = PathBuf::from(".canon").join(".quarantine");
+    path.push(local_path.strip_prefix(".canon").unwrap_or(&local_path));
+    Ok(path)
+}
+
+/// Write package files plus manifest and signature into a directory
+fn write_package(dir: &Path, package: &DownloadedPackage) -> CanonResult<()> {
+    fs::create_dir_all(dir).map_err(CanonError::Io)?;
+
+    for (path, content) in package.all_files() {
+        let file_path = dir.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).map_err(CanonError::Io)?;
+        }
+        fs::write(file_path, content).map_err(CanonError::Io)?;
     }
 
     Ok(())
 }
 
-async fn fetch_file(url: &str) -> CanonResult<String> {
-    let client = reqwest::Client::builder()
-        .user_agent("canon-cli/0.2.6")
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .map_err(|e| CanonError::Network {
-            message: format!("Failed to create HTTP client: {}", e),
-        })?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::registry::memory::{unsigned_package, MemoryRegistry};
+    use canon_protocol::{public_key_to_base64, PublisherKey, PublisherKeys};
+    use chrono::{Duration as ChronoDuration, Utc};
+    use ed25519_dalek::SigningKey;
+    use std::collections::HashMap;
+
+    fn keyring() -> PublisherKeyring {
+        PublisherKeyring::new(HttpClient::from_config(&Config::defaults()).unwrap())
+    }
+
+    const CANON_YML: &str = "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: 1.0.0\n  publisher: acme.com\n";
+
+    /// acme.com/api@1.0.0, signed with key-1
+    fn signed_package() -> DownloadedPackage {
+        let mut package = unsigned_package(CANON_YML, &[("artifacts/README.md", "# API")]);
+        let manifest: CanonManifest =
+            serde_yaml::from_slice(package.manifest.as_ref().unwrap()).unwrap();
+        let signature =
+            CanonSignature::sign(&manifest, "key-1", &SigningKey::from_bytes(&[7u8; 32]));
+        package.signature = Some(serde_yaml::to_string(&signature).unwrap().into_bytes());
+        package
+    }
 
-    let response = client
-        .get(url)
-        .send()
+    /// A keyring holding acme.com's key-1, revoked or not
+    fn signed_keyring(revoked: bool) -> PublisherKeyring {
+        let keys = PublisherKeys {
+            version: "1.0".to_string(),
+            keys: HashMap::from([(
+                "key-1".to_string(),
+                PublisherKey {
+                    algorithm: "ed25519".to_string(),
+                    public_key: public_key_to_base64(&SigningKey::from_bytes(&[7u8; 32])),
+                    created_at: Utc::now() - ChronoDuration::days(1),
+                    expires_at: None,
+                    revoked,
+                    usage: "signing".to_string(),
+                },
+            )]),
+            revoked_keys: HashMap::new(),
+        };
+        PublisherKeyring::with_keys(
+            HttpClient::from_config(&Config::defaults()).unwrap(),
+            "acme.com",
+            keys,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_install_from_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = RegistryLocation::parse("https://registry.example.com").unwrap();
+        let registry = MemoryRegistry::new();
+        registry.insert(
+            "acme.com/api@1.0.0",
+            unsigned_package(CANON_YML, &[("artifacts/README.md", "# API")]),
+        );
+        let dep = Dependency::parse("acme.com/api@1.0.0").unwrap();
+        let canon_dir = CanonDir::lock(dir.path()).unwrap();
+
+        // Unsigned packages need --allow-unsigned
+        let result = install_dependency(
+            &dep,
+            &canon_dir,
+            &location,
+            &registry,
+            false,
+            &keyring(),
+            None,
+        )
+        .await;
+        assert!(result.is_err());
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = PackageCache::new(cache_dir.path().to_path_buf());
+        let verification = install_dependency(
+            &dep,
+            &canon_dir,
+            &location,
+            &registry,
+            true,
+            &keyring(),
+            Some(&cache),
+        )
         .await
-        .map_err(|e| CanonError::Network {
-            message: format!("Failed to fetch {}: {}", url, e),
-        })?;
+        .unwrap();
+        assert_eq!(verification, Verification::Unsigned);
+        assert!(cache.find(&location, &dep, None).unwrap().is_some());
+        let installed = dir.path().join(location.local_path(&dep).unwrap());
+        assert_eq!(
+            fs::read_to_string(installed.join("artifacts/README.md")).unwrap(),
+            "# API"
+        );
+        assert!(is_complete_package(&installed));
 
-    if !response.status().is_success() {
-        return Err(CanonError::Network {
-            message: format!("Failed to fetch {} (status: {})", url, response.status()),
-        });
+        // canon.yml alone is not an install when the manifest lists more
+        fs::remove_file(installed.join("artifacts/README.md")).unwrap();
+        assert!(!is_complete_package(&installed));
     }
 
-    response.text().await.map_err(|e| CanonError::Network {
-        message: format!("Failed to read response: {}", e),
-    })
+    #[tokio::test]
+    async fn test_tampered_package_is_quarantined() {
+        let dir = tempfile::tempdir().unwrap();
+        let location = RegistryLocation::parse("https://registry.example.com").unwrap();
+        let registry = MemoryRegistry::new();
+        let mut package = unsigned_package(CANON_YML, &[("artifacts/README.md", "# API")]);
+        package
+            .files
+            .insert("artifacts/README.md".to_string(), b"# Tampered".to_vec());
+        registry.insert("acme.com/api@1.0.0", package);
+        let dep = Dependency::parse("acme.com/api@1.0.0").unwrap();
+        let canon_dir = CanonDir::lock(dir.path()).unwrap();
+
+        let result = install_dependency(
+            &dep,
+            &canon_dir,
+            &location,
+            &registry,
+            true,
+            &keyring(),
+            None,
+        )
+        .await;
+        assert!(result.unwrap_err().to_string().contains("modified"));
+        assert!(!dir.path().join(location.local_path(&dep).unwrap()).exists());
+        assert!(dir
+            .path()
+            .join(quarantine_path(&location, &dep).unwrap())
+            .join("canon.yml")
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn test_package_served_under_other_coordinates_is_quarantined() {
+        let package = signed_package();
+        let keyring = signed_keyring(false);
+
+        let location = RegistryLocation::parse("https://registry.example.com").unwrap();
+        let registry = MemoryRegistry::new();
+        registry.insert("acme.com/api@1.0.0", package.clone());
+        registry.insert("acme.com/api@2.0.0", package.clone());
+        registry.insert("acme.com/other@1.0.0", package);
+
+        let dir = tempfile::tempdir().unwrap();
+        let canon_dir = CanonDir::lock(dir.path()).unwrap();
+        for uri in [
+            "acme.com/api@1.0.0",
+            "acme.com/api@2.0.0",
+            "acme.com/other@1.0.0",
+        ] {
+            let dep = Dependency::parse(uri).unwrap();
+            let result = install_dependency(
+                &dep, &canon_dir, &location, &registry, false, &keyring, None,
+            )
+            .await;
+
+            if uri == "acme.com/api@1.0.0" {
+                assert!(matches!(result, Ok(Verification::Signed { .. })));
+                continue;
+            }
+            let err = result.unwrap_err().to_string();
+            assert!(
+                err.contains(&format!("describes acme.com/api@1.0.0, not {}", uri)),
+                "{}",
+                err
+            );
+            assert!(!dir.path().join(location.local_path(&dep).unwrap()).exists());
+            assert!(dir
+                .path()
+                .join(quarantine_path(&location, &dep).unwrap())
+                .join("canon.yml")
+                .exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_package_signed_with_revoked_key_is_a_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let location = RegistryLocation::parse("https://registry.example.com").unwrap();
+        let registry = MemoryRegistry::new();
+        let canon_dir = CanonDir::lock(dir.path()).unwrap();
+        let dep = Dependency::parse("acme.com/api@1.0.0").unwrap();
+        PackageCache::new(cache_dir.path().to_path_buf())
+            .store(
+                &location,
+                &dep,
+                &signed_package(),
+                &Verification::Signed {
+                    key_id: "key-1".to_string(),
+                },
+            )
+            .unwrap();
+
+        let source = |revoked, offline| InstallSource {
+            canon_dir: &canon_dir,
+            location: &location,
+            registry: &registry,
+            lock: None,
+            cache: Some(PackageCache::new(cache_dir.path().to_path_buf())),
+            frozen: false,
+            offline,
+            allow_unsigned: false,
+            keyring: signed_keyring(revoked),
+            progress: MultiProgress::new(),
+            outcomes: Mutex::default(),
+        };
+
+        assert!(source(false, false).cached(&dep).await.is_some());
+        assert!(source(true, false).cached(&dep).await.is_none());
+        // Offline the publisher's keys cannot be fetched, so the entry is trusted
+        assert!(source(true, true).cached(&dep).await.is_some());
+    }
 }
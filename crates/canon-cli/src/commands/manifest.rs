@@ -0,0 +1,47 @@
+use crate::core::manifest::{generate_manifest, write_manifest};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::CanonManifest;
+use console::style;
+use std::path::PathBuf;
+
+pub async fn run_manifest(path: Option<String>) -> CanonResult<()> {
+    let package_dir = match path {
+        Some(p) => PathBuf::from(p),
+        None => std::env::current_dir().map_err(|e| CanonError::Command {
+            message: format!("Failed to get current directory: {}", e),
+        })?,
+    };
+
+    println!(
+        "{} {}",
+        style("Generating manifest for").cyan().bold(),
+        package_dir.display()
+    );
+    println!();
+
+    let manifest = generate_manifest(&package_dir)?;
+    write_manifest(&package_dir, &manifest)?;
+
+    for file in &manifest.files {
+        println!(
+            "  {} {} ({} bytes)",
+            style("✓").green(),
+            file.path,
+            file.size
+        );
+    }
+    println!();
+    println!(
+        "{} {} ({} files, {} bytes)",
+        style("✓ Wrote").green().bold(),
+        CanonManifest::FILE_NAME,
+        manifest.file_count,
+        manifest.total_size
+    );
+    println!(
+        "  Canonical hash: {}",
+        style(&manifest.canonical_hash).dim()
+    );
+
+    Ok(())
+}
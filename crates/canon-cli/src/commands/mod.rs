@@ -1,29 +1,64 @@
 pub mod add;
 pub mod build;
+pub mod cache;
 pub mod clean;
 pub mod config;
 pub mod init;
 pub mod install;
+pub mod manifest;
+pub mod outdated;
 pub mod publish;
+pub mod remove;
+pub mod tree;
+pub mod update;
 pub mod validate;
+pub mod verify;
+pub mod why;
 
-use crate::cli::{Commands, ConfigCommands};
+use crate::cli::{CacheCommands, Commands, ConfigCommands};
+use crate::config::Config;
 use crate::utils::CanonResult;
 
-pub async fn handle_command(command: Commands) -> CanonResult<()> {
+pub async fn handle_command(command: Commands, config: &Config) -> CanonResult<()> {
     match command {
         Commands::Init {
             force,
             non_interactive,
-        } => init::run_init(force, non_interactive).await,
-        Commands::Install => install::run_install().await,
-        Commands::Add { uri } => add::run_add(&uri).await,
+        } => init::run_init(config, force, non_interactive).await,
+        Commands::Install {
+            locked,
+            frozen,
+            allow_unsigned,
+            parallel,
+        } => install::run_install(config, locked, frozen, allow_unsigned, parallel).await,
+        Commands::Add { uri } => add::run_add(config, &uri).await,
+        Commands::Outdated { format, fail } => {
+            outdated::run_outdated(config, format.as_deref(), fail).await
+        }
+        Commands::Remove { uri } => remove::run_remove(config, &uri).await,
+        Commands::Tree {
+            depth,
+            invert,
+            format,
+        } => tree::run_tree(config, depth, invert, format.as_deref()).await,
+        Commands::Why { uri } => why::run_why(config, &uri).await,
+        Commands::Update {
+            uris,
+            dry_run,
+            major,
+            allow_unsigned,
+        } => update::run_update(config, &uris, dry_run, major, allow_unsigned).await,
         Commands::Validate {
             path,
             strict,
             schema,
             fix,
-        } => validate::run_validate(path, strict, schema, fix).await,
+        } => validate::run_validate(config, path, strict, schema, fix).await,
+        Commands::Verify {
+            uris,
+            allow_unsigned,
+        } => verify::run_verify(config, uris, allow_unsigned).await,
+        Commands::Manifest { path } => manifest::run_manifest(path).await,
         Commands::Build {
             engine,
             output,
@@ -31,20 +66,32 @@ pub async fn handle_command(command: Commands) -> CanonResult<()> {
             key,
             no_cache,
             parallel,
-        } => build::run_build(engine, output, sign, key, no_cache, parallel).await,
+        } => build::run_build(config, engine, output, sign, key, no_cache, parallel).await,
         Commands::Publish {
             registry,
             token,
             dry_run,
             skip_verification,
-        } => publish::run_publish(registry, token, dry_run, skip_verification).await,
+        } => publish::run_publish(config, registry, token, dry_run, skip_verification).await,
         Commands::Clean { all, purge } => clean::run_clean(all, purge).await,
         Commands::Config { command } => match command {
-            ConfigCommands::Get { key } => config::get_config(&key).await,
-            ConfigCommands::Set { key, value } => config::set_config(&key, &value).await,
-            ConfigCommands::Unset { key } => config::unset_config(&key).await,
-            ConfigCommands::List => config::list_config().await,
-            ConfigCommands::Edit => config::edit_config().await,
+            ConfigCommands::Get { key } => config::get_config(config, &key).await,
+            ConfigCommands::Set {
+                key,
+                value,
+                project,
+            } => config::set_config(config, &key, &value, project).await,
+            ConfigCommands::Unset { key, project } => {
+                config::unset_config(config, &key, project).await
+            }
+            ConfigCommands::List => config::list_config(config).await,
+            ConfigCommands::Edit { project } => config::edit_config(config, project).await,
+        },
+        Commands::Cache { command } => match command {
+            CacheCommands::List => cache::list_cache(config).await,
+            CacheCommands::Prune { older_than } => cache::prune_cache(config, older_than).await,
+            CacheCommands::Verify => cache::verify_cache(config).await,
+            CacheCommands::Clean => cache::clean_cache(config).await,
         },
     }
 }
@@ -0,0 +1,322 @@
+use crate::config::Config;
+use crate::core::canon_dir::CanonDir;
+use crate::core::http::HttpClient;
+use crate::core::registry::{Registry, RegistryLocation};
+use crate::core::resolver::{Resolver, SpecSource, Upgrade};
+use crate::core::store::{installed_versions, PeekSource};
+use crate::core::{CanonSpecification, Dependency};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::semver::Version;
+use canon_protocol::CanonLock;
+use console::style;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::fs;
+
+/// Registry listings requested at once
+const PARALLEL: usize = 8;
+
+/// One row of the report
+#[derive(Debug, Serialize)]
+struct OutdatedPackage {
+    package: String,
+    current: String,
+    /// Newest version every range on the package allows
+    wanted: String,
+    /// Newest release on the registry
+    latest: Option<String>,
+    registry: String,
+    direct: bool,
+    required_by: Vec<String>,
+}
+
+impl OutdatedPackage {
+    fn is_outdated(&self) -> bool {
+        let current = Version::parse(&self.current).ok();
+        [Some(&self.wanted), self.latest.as_ref()]
+            .into_iter()
+            .flatten()
+            .any(|v| Version::parse(v).ok() > current)
+    }
+}
+
+pub async fn run_outdated(config: &Config, format: Option<&str>, fail: bool) -> CanonResult<()> {
+    let json = match format.unwrap_or("table") {
+        "table" => false,
+        "json" => true,
+        other => {
+            return Err(CanonError::Config {
+                message: format!("Unknown format '{}' (expected table or json)", other),
+            })
+        }
+    };
+
+    let location = RegistryLocation::from_config(config)?;
+    let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
+        message: format!("Failed to get current directory: {}", e),
+    })?;
+
+    let canon_yml_path = current_dir.join("canon.yml");
+    if !canon_yml_path.exists() {
+        return Err(CanonError::Command {
+            message: "No canon.yml found. Run 'canon init' first.".to_string(),
+        });
+    }
+
+    let yaml_content = fs::read_to_string(&canon_yml_path).map_err(CanonError::Io)?;
+    let spec: CanonSpecification =
+        serde_yaml::from_str(&yaml_content).map_err(|e| CanonError::Config {
+            message: format!("Failed to parse canon.yml: {}", e),
+        })?;
+    let roots = spec
+        .dependencies()
+        .iter()
+        .map(|d| Dependency::parse(d).map_err(CanonError::Protocol))
+        .collect::<CanonResult<Vec<_>>>()?;
+
+    // Nothing is written, but an install in progress is waited out
+    let _canon_dir = CanonDir::lock_shared(&current_dir)?;
+    let lock =
+        CanonLock::load(&current_dir.join(CanonLock::FILE_NAME)).map_err(CanonError::Protocol)?;
+
+    let http = HttpClient::from_config(config)?;
+    let registry = location.connect(&http)?;
+    let source = PeekSource {
+        base_dir: &current_dir,
+        location: &location,
+        registry: &registry,
+    };
+
+    // What is locked or installed now, and what `canon update` would move to
+    let current = Resolver::new(lock.as_ref())
+        .parallel(PARALLEL)
+        .resolve(&roots, &InstalledFirst(&source))
+        .await?;
+    let wanted = Resolver::new(lock.as_ref())
+        .parallel(PARALLEL)
+        .upgrade(Upgrade::All)
+        .resolve(&roots, &source)
+        .await?;
+    if let Some((uri, e)) = current.failures.iter().chain(&wanted.failures).next() {
+        return Err(CanonError::Command {
+            message: format!("Could not read {}: {}", uri, e),
+        });
+    }
+
+    let latest: Vec<CanonResult<Vec<Version>>> = stream::iter(current.packages.values())
+        .map(|package| source.versions(&package.dependency))
+        .buffered(PARALLEL)
+        .collect()
+        .await;
+
+    let mut rows = Vec::new();
+    for ((key, package), versions) in current.packages.iter().zip(latest) {
+        let newest = versions?.into_iter().filter(|v| v.pre.is_empty()).max();
+        rows.push(OutdatedPackage {
+            package: key.clone(),
+            current: package.dependency.version.clone().unwrap_or_default(),
+            wanted: wanted
+                .packages
+                .get(key)
+                .and_then(|p| p.dependency.version.clone())
+                .unwrap_or_default(),
+            latest: newest.map(|v| v.to_string()),
+            registry: lock
+                .as_ref()
+                .and_then(|l| l.find(&package.dependency))
+                .map(|p| p.registry.clone())
+                .unwrap_or_else(|| location.url().to_string()),
+            direct: package.direct,
+            required_by: package.required_by.clone(),
+        });
+    }
+
+    let outdated = rows.iter().filter(|row| row.is_outdated()).count();
+    if json {
+        let output = serde_json::to_string_pretty(&rows).map_err(|e| CanonError::Command {
+            message: format!("Failed to serialize report: {}", e),
+        })?;
+        println!("{}", output);
+    } else {
+        print_table(&rows);
+    }
+
+    if fail && outdated > 0 {
+        return Err(CanonError::Command {
+            message: format!("{} dependencies are outdated", outdated),
+        });
+    }
+    Ok(())
+}
+
+/// Offers the versions installed in `.canon/` for packages canon.lock does
+/// not settle, so "current" is what is there rather than the newest allowed
+///
+/// Falls back to the registry listing when no installed version satisfies
+/// the range, as for a package that has not been installed yet.
+struct InstalledFirst<'a, R>(&'a PeekSource<'a, R>);
+
+impl<R: Registry> SpecSource for InstalledFirst<'_, R> {
+    async fn load(&self, dep: &Dependency) -> CanonResult<CanonSpecification> {
+        self.0.load(dep).await
+    }
+
+    async fn versions(&self, dep: &Dependency) -> CanonResult<Vec<Version>> {
+        let req = dep.version_req().map_err(CanonError::Protocol)?;
+        let installed = installed_versions(self.0.base_dir, self.0.location, dep)?;
+        if installed.iter().any(|version| req.matches(version)) {
+            return Ok(installed);
+        }
+        self.0.versions(dep).await
+    }
+}
+
+fn print_table(rows: &[OutdatedPackage]) {
+    if rows.is_empty() {
+        println!("No dependencies");
+        return;
+    }
+
+    let names: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            if row.direct {
+                row.package.clone()
+            } else {
+                format!("{} (via {})", row.package, row.required_by.join(", "))
+            }
+        })
+        .collect();
+    let name_width = column_width("Package", names.iter().map(String::as_str));
+    let current_width = column_width("Current", rows.iter().map(|row| row.current.as_str()));
+    let wanted_width = column_width("Wanted", rows.iter().map(|row| row.wanted.as_str()));
+    let latest_width = column_width(
+        "Latest",
+        rows.iter().map(|row| row.latest.as_deref().unwrap_or("-")),
+    );
+
+    println!(
+        "{:<name_width$}  {:<current_width$}  {:<wanted_width$}  {:<latest_width$}  {}",
+        style("Package").bold(),
+        style("Current").bold(),
+        style("Wanted").bold(),
+        style("Latest").bold(),
+        style("Registry").bold(),
+    );
+    for (row, name) in rows.iter().zip(&names) {
+        let name = if row.is_outdated() {
+            style(name.as_str()).yellow()
+        } else {
+            style(name.as_str())
+        };
+        let wanted = if row.wanted != row.current {
+            style(row.wanted.as_str()).green()
+        } else {
+            style(row.wanted.as_str())
+        };
+        let latest = row.latest.as_deref().unwrap_or("-");
+        let latest = if latest != row.wanted && latest != "-" {
+            style(latest).magenta()
+        } else {
+            style(latest)
+        };
+        println!(
+            "{:<name_width$}  {:<current_width$}  {:<wanted_width$}  {:<latest_width$}  {}",
+            name,
+            row.current,
+            wanted,
+            latest,
+            style(&row.registry).dim(),
+        );
+    }
+}
+
+fn column_width<'a>(header: &str, values: impl Iterator<Item = &'a str>) -> usize {
+    values.map(str::len).max().unwrap_or(0).max(header.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_outdated() {
+        let row = |current: &str, wanted: &str, latest: Option<&str>| OutdatedPackage {
+            package: "acme.com/api".to_string(),
+            current: current.to_string(),
+            wanted: wanted.to_string(),
+            latest: latest.map(str::to_string),
+            registry: "https://registry.example.com".to_string(),
+            direct: true,
+            required_by: Vec::new(),
+        };
+
+        assert!(!row("1.0.0", "1.0.0", Some("1.0.0")).is_outdated());
+        assert!(!row("1.0.0", "1.0.0", None).is_outdated());
+        assert!(row("1.0.0", "1.0.0", Some("2.0.0")).is_outdated());
+        assert!(row("1.0.0", "1.1.0", Some("1.1.0")).is_outdated());
+    }
+
+    #[tokio::test]
+    async fn test_current_is_the_installed_version_without_a_lock() {
+        use crate::core::registry::memory::{unsigned_package, MemoryRegistry};
+
+        let dir = tempfile::tempdir().unwrap();
+        let location = RegistryLocation::parse("https://registry.example.com").unwrap();
+        let registry = MemoryRegistry::new();
+        for version in ["1.0.0", "1.1.0"] {
+            let canon_yml = format!(
+                "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: {}\n  publisher: acme.com\n",
+                version
+            );
+            registry.insert(
+                &format!("acme.com/api@{}", version),
+                unsigned_package(&canon_yml, &[]),
+            );
+        }
+
+        // 1.0.0 is installed, with no canon.lock to say so
+        let installed = Dependency::parse("acme.com/api@1.0.0").unwrap();
+        let package_dir = dir.path().join(location.local_path(&installed).unwrap());
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(
+            package_dir.join("canon.yml"),
+            "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: 1.0.0\n  publisher: acme.com\n",
+        )
+        .unwrap();
+
+        let source = PeekSource {
+            base_dir: dir.path(),
+            location: &location,
+            registry: &registry,
+        };
+        let roots = [Dependency::parse("acme.com/api@^1.0.0").unwrap()];
+        let version = |resolution: &crate::core::resolver::Resolution| {
+            resolution.packages["acme.com/api"]
+                .dependency
+                .version
+                .clone()
+        };
+
+        let current = Resolver::new(None)
+            .resolve(&roots, &InstalledFirst(&source))
+            .await
+            .unwrap();
+        assert_eq!(version(&current).as_deref(), Some("1.0.0"));
+
+        let wanted = Resolver::new(None)
+            .upgrade(Upgrade::All)
+            .resolve(&roots, &source)
+            .await
+            .unwrap();
+        assert_eq!(version(&wanted).as_deref(), Some("1.1.0"));
+
+        // An installed version outside the range does not count
+        let roots = [Dependency::parse("acme.com/api@^1.1.0").unwrap()];
+        let current = Resolver::new(None)
+            .resolve(&roots, &InstalledFirst(&source))
+            .await
+            .unwrap();
+        assert_eq!(version(&current).as_deref(), Some("1.1.0"));
+    }
+}
@@ -1,83 +1,18 @@
+use crate::commands::build::{build_package, BuildOptions};
+use crate::config::Config;
+use crate::core::http::HttpClient;
+use crate::core::integrity::compare_files;
+use crate::core::manifest::scan_package;
+use crate::core::registry::{PublishPackage, Registry, RegistryLocation, VerificationConfig};
 use crate::utils::{CanonError, CanonResult};
-use canon_protocol::CanonSpecification;
+use canon_protocol::{CanonManifest, CanonSpecification};
 use console::style;
 use dialoguer::{theme::ColorfulTheme, Input, Password};
-use reqwest;
-use serde::Deserialize;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-/// Registry capability discovery response
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct RegistryCapabilities {
-    #[serde(default)]
-    name: String,
-    url: String,
-    api_version: String,
-    read_only: bool,
-    #[serde(default)]
-    authentication: Option<AuthenticationConfig>,
-    #[serde(default)]
-    verification: Option<VerificationConfig>,
-    endpoints: EndpointsConfig,
-}
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct AuthenticationConfig {
-    required: bool,
-    #[serde(default)]
-    methods: Vec<String>,
-    #[serde(default)]
-    endpoints: Option<AuthEndpoints>,
-}
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct AuthEndpoints {
-    #[serde(default)]
-    token: Option<String>,
-    #[serde(default)]
-    oauth: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct VerificationConfig {
-    methods: Vec<String>,
-    #[serde(default)]
-    dns_txt: Option<DnsVerificationConfig>,
-    #[serde(default)]
-    https_file: Option<HttpsVerificationConfig>,
-}
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct DnsVerificationConfig {
-    prefix: String,
-    format: String,
-    #[serde(default)]
-    ttl: Option<u64>,
-}
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct HttpsVerificationConfig {
-    path: String,
-    format: String,
-}
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct EndpointsConfig {
-    discovery: String,
-    packages: String,
-    download: String,
-    #[serde(default)]
-    publish: Option<String>,
-}
-
 pub async fn run_publish(
+    config: &Config,
     registry: Option<String>,
     token: Option<String>,
     dry_run: bool,
@@ -116,25 +51,38 @@ pub async fn run_publish(
     );
     println!();
 
-    // Step 2: Check if package is built in localhost
+    // Step 2: Make sure the canonical package is built in localhost
+    let current_dir = canon_yml_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
     let local_path = PathBuf::from(".canon")
         .join("localhost")
         .join(publisher)
         .join(id)
         .join(version);
 
-    if !local_path.exists() {
-        // For now, we'll just copy the current canon.yml to localhost
-        // In the future, the build command will handle this
-        println!("{}", style("Building canonical form...").dim());
-
-        fs::create_dir_all(&local_path).map_err(CanonError::Io)?;
-        let dest_canon = local_path.join("canon.yml");
-        fs::copy(&canon_yml_path, &dest_canon).map_err(CanonError::Io)?;
-
-        println!("{} Built to localhost", style("✓").green());
-        println!();
-    }
+    let manifest = match read_built_manifest(&local_path, &spec)? {
+        Some(manifest) => manifest,
+        None => {
+            println!("{}", style("Building canonical form...").dim());
+
+            let options = BuildOptions {
+                engine: None,
+                output: PathBuf::from(".canon"),
+                sign: false,
+                key: None,
+                no_cache: false,
+                parallel: None,
+                registry: RegistryLocation::from_config(config)?,
+            };
+            let package = build_package(&current_dir, &options).await?;
+
+            println!("{} Built to localhost", style("✓").green());
+            println!();
+            package.manifest
+        }
+    };
 
     // Step 3: Get registry URL
     let registry_url = match registry {
@@ -147,11 +95,13 @@ pub async fn run_publish(
 
     println!("Registry: {}", style(&registry_url).cyan());
     println!();
+    let registry =
+        RegistryLocation::parse(&registry_url)?.connect(&HttpClient::from_config(config)?)?;
 
     // Step 4: Check registry capabilities
     println!("{}", style("Checking registry capabilities...").dim());
 
-    let capabilities = match discover_registry_capabilities(&registry_url).await {
+    let capabilities = match registry.discover().await {
         Ok(caps) => caps,
         Err(_) => {
             return Err(CanonError::Command {
@@ -228,6 +178,7 @@ pub async fn run_publish(
         println!("  Registry: {}", registry_url);
         println!("  Package: {}/{}@{}", publisher, id, version);
         println!("  From: {}", local_path.display());
+        println!("  Canonical hash: {}", manifest.canonical_hash);
         if auth_token.is_some() {
             println!("  Auth: Token provided");
         }
@@ -236,16 +187,13 @@ pub async fn run_publish(
     } else {
         println!("{}", style("Publishing package...").dim());
 
-        publish_to_registry(
-            &registry_url,
-            &capabilities.endpoints.publish.unwrap(),
-            publisher,
-            id,
-            version,
-            &local_path,
-            auth_token,
-        )
-        .await?;
+        registry
+            .publish(&PublishPackage {
+                dir: &local_path,
+                manifest: &manifest,
+                auth_token: auth_token.as_deref(),
+            })
+            .await?;
 
         println!();
         println!("{} Published successfully!", style("✓").green().bold());
@@ -257,14 +205,50 @@ pub async fn run_publish(
     Ok(())
 }
 
+/// Read the manifest of an existing build, if that build is still current
+///
+/// A build is current when its canon.yml matches the canonical form of the
+/// project's canon.yml and its files still match its manifest.
+fn read_built_manifest(
+    local_path: &Path,
+    spec: &CanonSpecification,
+) -> CanonResult<Option<CanonManifest>> {
+    let manifest_path = local_path.join(CanonManifest::FILE_NAME);
+    let built_canon_yml = local_path.join("canon.yml");
+    if !manifest_path.exists() || !built_canon_yml.exists() {
+        return Ok(None);
+    }
+
+    let expected = spec.to_canonical_yaml().map_err(CanonError::Protocol)?;
+    let built = fs::read_to_string(&built_canon_yml).map_err(CanonError::Io)?;
+    if built != expected {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&manifest_path).map_err(CanonError::Io)?;
+    let manifest: CanonManifest = match serde_yaml::from_str(&content) {
+        Ok(manifest) => manifest,
+        Err(_) => return Ok(None),
+    };
+    let (files, _) = scan_package(local_path)?;
+    if !compare_files(&manifest, &files).is_clean() {
+        return Ok(None);
+    }
+
+    Ok(Some(manifest))
+}
+
 fn prompt_for_registry() -> CanonResult<String> {
     Input::<String>::with_theme(&ColorfulTheme::default())
         .with_prompt("Registry URL")
         .validate_with(|input: &String| -> Result<(), &str> {
-            if input.starts_with("https://") || input.starts_with("http://") {
+            if input.starts_with("https://")
+                || input.starts_with("http://")
+                || input.starts_with("file://")
+            {
                 Ok(())
             } else {
-                Err("Registry URL must start with https:// or http://")
+                Err("Registry URL must start with https://, http:// or file://")
             }
         })
         .interact()
@@ -273,38 +257,6 @@ fn prompt_for_registry() -> CanonResult<String> {
         })
 }
 
-async fn discover_registry_capabilities(registry_url: &str) -> CanonResult<RegistryCapabilities> {
-    let discovery_url = format!("{}/.well-known/canon-registry", registry_url);
-
-    let client = reqwest::Client::builder()
-        .user_agent("canon-cli/0.2.10")
-        .build()
-        .map_err(|e| CanonError::Network {
-            message: format!("Failed to create HTTP client: {}", e),
-        })?;
-
-    let response = client
-        .get(&discovery_url)
-        .send()
-        .await
-        .map_err(|e| CanonError::Network {
-            message: format!("Failed to fetch registry capabilities: {}", e),
-        })?;
-
-    if !response.status().is_success() {
-        return Err(CanonError::Network {
-            message: format!("Registry discovery failed (status: {})", response.status()),
-        });
-    }
-
-    let capabilities: RegistryCapabilities =
-        response.json().await.map_err(|e| CanonError::Network {
-            message: format!("Failed to parse registry capabilities: {}", e),
-        })?;
-
-    Ok(capabilities)
-}
-
 async fn verify_domain_ownership(
     publisher: &str,
     _registry_url: &str,
@@ -350,66 +302,3 @@ async fn verify_domain_ownership(
         message: "No supported verification method available".to_string(),
     })
 }
-
-async fn publish_to_registry(
-    registry_url: &str,
-    publish_endpoint: &str,
-    publisher: &str,
-    id: &str,
-    version: &str,
-    local_path: &Path,
-    auth_token: Option<String>,
-) -> CanonResult<()> {
-    // Read the canonical form
-    let canon_yml_path = local_path.join("canon.yml");
-    let canon_yml_content = fs::read_to_string(&canon_yml_path).map_err(CanonError::Io)?;
-
-    // Construct the publish URL
-    let publish_url = if publish_endpoint.starts_with("http") {
-        publish_endpoint.to_string()
-    } else {
-        format!("{}{}", registry_url, publish_endpoint)
-    };
-
-    // Create the request
-    let client = reqwest::Client::builder()
-        .user_agent("canon-cli/0.2.10")
-        .build()
-        .map_err(|e| CanonError::Network {
-            message: format!("Failed to create HTTP client: {}", e),
-        })?;
-
-    let mut request = client
-        .post(&publish_url)
-        .header("Content-Type", "application/x-yaml");
-
-    if let Some(token) = auth_token {
-        request = request.header("Authorization", format!("Bearer {}", token));
-    }
-
-    // Add package metadata headers
-    request = request
-        .header("X-Canon-Publisher", publisher)
-        .header("X-Canon-Id", id)
-        .header("X-Canon-Version", version);
-
-    // Send the request
-    let response =
-        request
-            .body(canon_yml_content)
-            .send()
-            .await
-            .map_err(|e| CanonError::Network {
-                message: format!("Failed to publish package: {}", e),
-            })?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(CanonError::Network {
-            message: format!("Publication failed (status: {}): {}", status, body),
-        });
-    }
-
-    Ok(())
-}
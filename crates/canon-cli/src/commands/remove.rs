@@ -0,0 +1,222 @@
+use crate::commands::install::write_lock;
+use crate::config::Config;
+use crate::core::canon_dir::CanonDir;
+use crate::core::registry::RegistryLocation;
+use crate::core::resolver::{package_key, Resolver};
+use crate::core::store::{list_installed, InstalledSource};
+use crate::core::{CanonSpecification, Dependency};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::CanonLock;
+use console::style;
+use serde_yaml::Value;
+use std::collections::BTreeSet;
+use std::fs;
+
+pub async fn run_remove(config: &Config, uri: &str) -> CanonResult<()> {
+    let location = RegistryLocation::from_config(config)?;
+    let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
+        message: format!("Failed to get current directory: {}", e),
+    })?;
+
+    let canon_yml_path = current_dir.join("canon.yml");
+    if !canon_yml_path.exists() {
+        return Err(CanonError::Command {
+            message: "No canon.yml found. Run 'canon init' first.".to_string(),
+        });
+    }
+
+    let target = Dependency::parse(uri).map_err(CanonError::Protocol)?;
+
+    let yaml_content = fs::read_to_string(&canon_yml_path).map_err(CanonError::Io)?;
+    let mut spec: CanonSpecification =
+        serde_yaml::from_str(&yaml_content).map_err(|e| CanonError::Config {
+            message: format!("Failed to parse canon.yml: {}", e),
+        })?;
+
+    let declared = spec.dependencies();
+    let entry = find_declared(&declared, &target, uri)?;
+    let remaining: Vec<String> = declared.iter().filter(|d| **d != entry).cloned().collect();
+
+    let canon_dir = CanonDir::lock(&current_dir)?;
+    let lock_path = current_dir.join(CanonLock::FILE_NAME);
+    let existing_lock = CanonLock::load(&lock_path).map_err(CanonError::Protocol)?;
+
+    // Work out what the remaining dependencies still need before changing anything
+    let roots = remaining
+        .iter()
+        .map(|d| Dependency::parse(d).map_err(CanonError::Protocol))
+        .collect::<CanonResult<Vec<_>>>()?;
+    let source = InstalledSource {
+        base_dir: &current_dir,
+        location: &location,
+    };
+    let resolution = Resolver::new(existing_lock.as_ref())
+        .resolve(&roots, &source)
+        .await?;
+    let needed: BTreeSet<String> = resolution
+        .packages
+        .values()
+        .map(|p| p.dependency.to_uri())
+        .collect();
+
+    if let Some(sequence) = spec
+        .content
+        .get_mut("dependencies")
+        .and_then(Value::as_sequence_mut)
+    {
+        sequence.retain(|value| value.as_str() != Some(entry.as_str()));
+    }
+    let yaml_content = serde_yaml::to_string(&spec).map_err(CanonError::Serialization)?;
+    fs::write(&canon_yml_path, yaml_content).map_err(CanonError::Io)?;
+
+    println!(
+        "{} {} from canon.yml",
+        style("Removed").green().bold(),
+        style(&entry).cyan()
+    );
+
+    if let Some(package) = resolution.packages.get(&package_key(&target)) {
+        let mut required_by = package.required_by.clone();
+        if package.direct {
+            required_by.insert(0, "canon.yml".to_string());
+        }
+        println!(
+            "  {} {} is still required by {}, keeping it",
+            style("⚠").yellow(),
+            package.dependency.to_uri(),
+            required_by.join(", ")
+        );
+    }
+
+    // Without the full closure some kept package might still need a candidate
+    if !resolution.failures.is_empty() {
+        println!(
+            "  {} Some dependencies are not installed, so nothing was pruned from .canon/",
+            style("⚠").yellow()
+        );
+        for (uri, e) in &resolution.failures {
+            println!("    - {}: {}", uri, e);
+        }
+    } else {
+        // Packages the old lock recorded, plus any installed version of the removed one
+        let mut candidates: BTreeSet<String> = existing_lock
+            .iter()
+            .flat_map(|lock| &lock.packages)
+            .filter(|p| p.registry == location.url())
+            .map(|p| p.to_dependency().to_uri())
+            .collect();
+        for package in list_installed(&current_dir.join(".canon"))? {
+            let dep = &package.dependency;
+            if package.registry == location.dir_name()
+                && dep.publisher == target.publisher
+                && dep.id == target.id
+            {
+                candidates.insert(package.uri());
+            }
+        }
+
+        for candidate in candidates.difference(&needed) {
+            let dep = Dependency::parse(candidate).map_err(CanonError::Protocol)?;
+            if canon_dir.remove_package(&current_dir.join(location.local_path(&dep)?))? {
+                println!("  {} Pruned {}", style("✓").green(), candidate);
+            }
+        }
+    }
+
+    if let Some(lock) = &existing_lock {
+        let packages = lock
+            .packages
+            .iter()
+            .filter(|p| needed.contains(&p.to_dependency().to_uri()))
+            .cloned()
+            .collect();
+        write_lock(
+            &lock_path,
+            existing_lock.as_ref(),
+            CanonLock::new(&remaining, packages),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The canon.yml entry `uri` refers to
+///
+/// Without a version `uri` may name any entry for that publisher/id, as
+/// long as there is only one; with a version it must match exactly.
+fn find_declared(declared: &[String], target: &Dependency, uri: &str) -> CanonResult<String> {
+    let same_package: Vec<&String> = declared
+        .iter()
+        .filter(|d| {
+            Dependency::parse(d)
+                .map(|dep| dep.publisher == target.publisher && dep.id == target.id)
+                .unwrap_or(false)
+        })
+        .collect();
+
+    if target.version.is_some() {
+        if let Some(entry) = same_package.iter().find(|d| {
+            d.as_str() == uri
+                || Dependency::parse(d).map(|dep| dep.to_uri()).ok() == Some(target.to_uri())
+        }) {
+            return Ok(entry.to_string());
+        }
+    } else if let [only] = same_package.as_slice() {
+        return Ok(only.to_string());
+    }
+
+    let listed = same_package
+        .iter()
+        .map(|d| format!("\n  - {}", d))
+        .collect::<String>();
+    let message = match same_package.as_slice() {
+        [] => format!("{} is not a dependency in canon.yml", uri),
+        [first, ..] if target.version.is_none() => format!(
+            "{} matches several dependencies in canon.yml:{}\nSpecify which one, e.g. 'canon remove {}'",
+            uri, listed, first
+        ),
+        _ => format!(
+            "{} is not a dependency in canon.yml; it declares:{}",
+            uri, listed
+        ),
+    };
+    Err(CanonError::Command { message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(declared: &[&str], uri: &str) -> CanonResult<String> {
+        let declared: Vec<String> = declared.iter().map(|d| d.to_string()).collect();
+        find_declared(&declared, &Dependency::parse(uri).unwrap(), uri)
+    }
+
+    #[test]
+    fn test_find_declared() {
+        let declared = [
+            "acme.com/api@1.0.0",
+            "acme.com/api@^2.0.0",
+            "acme.com/util@1.0.0",
+        ];
+
+        assert_eq!(
+            find(&declared, "acme.com/util").unwrap(),
+            "acme.com/util@1.0.0"
+        );
+        assert_eq!(
+            find(&declared, "acme.com/api@^2.0.0").unwrap(),
+            "acme.com/api@^2.0.0"
+        );
+
+        let ambiguous = find(&declared, "acme.com/api").unwrap_err().to_string();
+        assert!(ambiguous.contains("matches several"));
+        assert!(ambiguous.contains("acme.com/api@^2.0.0"));
+
+        let missing = find(&declared, "acme.com/api@3.0.0")
+            .unwrap_err()
+            .to_string();
+        assert!(missing.contains("it declares"));
+        assert!(find(&declared, "acme.com/other").is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use crate::config::Config;
+use crate::core::canon_dir::CanonDir;
+use crate::core::graph::{DependencyGraph, Edge, EdgeKind};
+use crate::core::registry::RegistryLocation;
+use crate::utils::{CanonError, CanonResult};
+use console::style;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+enum Format {
+    Text,
+    Dot,
+    Json,
+}
+
+pub async fn run_tree(
+    config: &Config,
+    depth: Option<usize>,
+    invert: bool,
+    format: Option<&str>,
+) -> CanonResult<()> {
+    let format = match format.unwrap_or("text") {
+        "text" => Format::Text,
+        "dot" => Format::Dot,
+        "json" => Format::Json,
+        other => {
+            return Err(CanonError::Config {
+                message: format!("Unknown format '{}' (expected text, dot or json)", other),
+            })
+        }
+    };
+
+    let graph = load_graph(config).await?;
+    let starts = if invert {
+        graph.leaves()
+    } else {
+        vec![graph.root.clone()]
+    };
+
+    match format {
+        Format::Text => {
+            let mut shown = HashSet::new();
+            let mut elided = false;
+            for start in &starts {
+                println!("{}", label(&graph, start));
+                elided |= print_children(
+                    &graph,
+                    start,
+                    invert,
+                    depth,
+                    "",
+                    &mut vec![start.clone()],
+                    &mut shown,
+                );
+            }
+            if elided {
+                println!();
+                println!("{}", style("(*) already shown above").dim());
+            }
+        }
+        Format::Dot => print_dot(&graph, &starts, invert, depth),
+        Format::Json => print_json(&graph, &starts, invert, depth)?,
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn load_graph(config: &Config) -> CanonResult<DependencyGraph> {
+    let location = RegistryLocation::from_config(config)?;
+    let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
+        message: format!("Failed to get current directory: {}", e),
+    })?;
+
+    // Nothing is written, but an install in progress is waited out
+    let _canon_dir = CanonDir::lock_shared(&current_dir)?;
+    DependencyGraph::load(&current_dir, &location).await
+}
+
+/// A package's name with a `(not installed)` marker where that applies
+fn label(graph: &DependencyGraph, uri: &str) -> String {
+    if graph.missing.contains(uri) {
+        format!("{} {}", uri, style("(not installed)").red())
+    } else {
+        uri.to_string()
+    }
+}
+
+/// Print the tree below `uri`, marking packages already expanded elsewhere
+/// with `(*)` and edges back to an ancestor with `(cycle)`
+///
+/// Returns whether any package was marked `(*)`.
+fn print_children(
+    graph: &DependencyGraph,
+    uri: &str,
+    invert: bool,
+    depth: Option<usize>,
+    prefix: &str,
+    path: &mut Vec<String>,
+    shown: &mut HashSet<String>,
+) -> bool {
+    if depth.is_some_and(|depth| path.len() > depth) {
+        return false;
+    }
+    shown.insert(uri.to_string());
+
+    let mut elided = false;
+    let edges = graph.neighbours(uri, invert);
+    for (i, edge) in edges.iter().enumerate() {
+        let last = i + 1 == edges.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        let mut line = edge.package.clone();
+        if edge.kind != EdgeKind::Dependency {
+            line.push_str(&format!(" {}", style(format!("({})", edge.kind)).dim()));
+        }
+        if graph.missing.contains(&edge.package) {
+            line.push_str(&format!(" {}", style("(not installed)").red()));
+        }
+
+        let has_children = !graph.neighbours(&edge.package, invert).is_empty();
+        if path.contains(&edge.package) {
+            println!("{}{}{} {}", prefix, branch, line, style("(cycle)").yellow());
+        } else if shown.contains(&edge.package) && has_children {
+            println!("{}{}{} {}", prefix, branch, line, style("(*)").dim());
+            elided = true;
+        } else {
+            println!("{}{}{}", prefix, branch, line);
+            path.push(edge.package.clone());
+            elided |= print_children(
+                graph,
+                &edge.package,
+                invert,
+                depth,
+                &format!("{}{}", prefix, indent),
+                path,
+                shown,
+            );
+            path.pop();
+        }
+    }
+    elided
+}
+
+/// Edges among packages within `depth` of `starts`, in display direction
+fn visible_edges(
+    graph: &DependencyGraph,
+    starts: &[String],
+    invert: bool,
+    depth: Option<usize>,
+) -> (BTreeSet<String>, Vec<(String, Edge)>) {
+    let nodes: BTreeSet<String> = graph
+        .depths(starts, invert)
+        .into_iter()
+        .filter(|(_, d)| depth.is_none_or(|depth| *d <= depth))
+        .map(|(uri, _)| uri)
+        .collect();
+
+    let edges = nodes
+        .iter()
+        .flat_map(|from| {
+            graph
+                .neighbours(from, invert)
+                .into_iter()
+                .filter(|edge| nodes.contains(&edge.package))
+                .map(move |edge| (from.clone(), edge))
+        })
+        .collect();
+    (nodes, edges)
+}
+
+fn print_dot(graph: &DependencyGraph, starts: &[String], invert: bool, depth: Option<usize>) {
+    let (nodes, edges) = visible_edges(graph, starts, invert, depth);
+
+    println!("digraph dependencies {{");
+    for uri in &nodes {
+        let shape = if *uri == graph.root { "box" } else { "ellipse" };
+        let style = if graph.missing.contains(uri) {
+            ", style=dashed"
+        } else {
+            ""
+        };
+        println!("  \"{}\" [shape={}{}];", uri, shape, style);
+    }
+    for (from, edge) in &edges {
+        let style = match edge.kind {
+            EdgeKind::Ref => ", style=dashed",
+            _ => "",
+        };
+        println!(
+            "  \"{}\" -> \"{}\" [label=\"{}\"{}];",
+            from, edge.package, edge.kind, style
+        );
+    }
+    println!("}}");
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    root: &'a str,
+    packages: BTreeMap<String, JsonPackage>,
+}
+
+#[derive(Serialize)]
+struct JsonPackage {
+    installed: bool,
+    requires: Vec<Edge>,
+    required_by: Vec<Edge>,
+}
+
+fn print_json(
+    graph: &DependencyGraph,
+    starts: &[String],
+    invert: bool,
+    depth: Option<usize>,
+) -> CanonResult<()> {
+    let (nodes, _) = visible_edges(graph, starts, invert, depth);
+    let within = |edges: Vec<Edge>| -> Vec<Edge> {
+        edges
+            .into_iter()
+            .filter(|edge| nodes.contains(&edge.package))
+            .collect()
+    };
+
+    let packages = nodes
+        .iter()
+        .map(|uri| {
+            let package = JsonPackage {
+                installed: !graph.missing.contains(uri),
+                requires: within(graph.neighbours(uri, false)),
+                required_by: within(graph.neighbours(uri, true)),
+            };
+            (uri.clone(), package)
+        })
+        .collect();
+
+    let output = serde_json::to_string_pretty(&JsonGraph {
+        root: &graph.root,
+        packages,
+    })
+    .map_err(|e| CanonError::Command {
+        message: format!("Failed to serialize graph: {}", e),
+    })?;
+    println!("{}", output);
+    Ok(())
+}
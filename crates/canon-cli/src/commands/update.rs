@@ -0,0 +1,392 @@
+use crate::commands::install::install_project;
+use crate::config::Config;
+use crate::core::canon_dir::CanonDir;
+use crate::core::http::HttpClient;
+use crate::core::registry::RegistryLocation;
+use crate::core::resolver::{package_key, Resolver, SpecSource, Upgrade};
+use crate::core::store::PeekSource;
+use crate::core::{CanonSpecification, Dependency};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::semver::Version;
+use canon_protocol::{CanonLock, LockedPackage, VersionOperator};
+use console::style;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+
+pub async fn run_update(
+    config: &Config,
+    uris: &[String],
+    dry_run: bool,
+    major: bool,
+    allow_unsigned: bool,
+) -> CanonResult<()> {
+    let location = RegistryLocation::from_config(config)?;
+    let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
+        message: format!("Failed to get current directory: {}", e),
+    })?;
+
+    let canon_yml_path = current_dir.join("canon.yml");
+    if !canon_yml_path.exists() {
+        return Err(CanonError::Command {
+            message: "No canon.yml found. Run 'canon init' first.".to_string(),
+        });
+    }
+
+    let yaml_content = fs::read_to_string(&canon_yml_path).map_err(CanonError::Io)?;
+    let mut spec: CanonSpecification =
+        serde_yaml::from_str(&yaml_content).map_err(|e| CanonError::Config {
+            message: format!("Failed to parse canon.yml: {}", e),
+        })?;
+    let declared = spec.dependencies();
+    let mut roots = declared
+        .iter()
+        .map(|d| Dependency::parse(d).map_err(CanonError::Protocol))
+        .collect::<CanonResult<Vec<_>>>()?;
+
+    let canon_dir = CanonDir::lock(&current_dir)?;
+    let lock_path = current_dir.join(CanonLock::FILE_NAME);
+    let existing_lock = CanonLock::load(&lock_path).map_err(CanonError::Protocol)?;
+
+    let upgrade = if uris.is_empty() {
+        Upgrade::All
+    } else {
+        let mut keys = BTreeSet::new();
+        for uri in uris {
+            let dep = Dependency::parse(uri).map_err(CanonError::Protocol)?;
+            let key = package_key(&dep);
+            let known = roots.iter().any(|root| package_key(root) == key)
+                || existing_lock
+                    .as_ref()
+                    .is_some_and(|lock| lock.find(&dep).is_some());
+            if !known {
+                return Err(CanonError::Command {
+                    message: format!("{} is not a dependency of this project", key),
+                });
+            }
+            keys.insert(key);
+        }
+        Upgrade::Only(keys)
+    };
+
+    let http = HttpClient::from_config(config)?;
+    let registry = location.connect(&http)?;
+    let source = PeekSource {
+        base_dir: &current_dir,
+        location: &location,
+        registry: &registry,
+    };
+
+    println!("{} for updates", style("Checking").cyan().bold());
+    println!();
+
+    // With --major, ranges in canon.yml that exclude the newest release are moved up to it
+    let mut rewrites = Vec::new();
+    if major {
+        for (entry, root) in declared.iter().zip(roots.iter_mut()) {
+            if !upgrade.includes(&package_key(root)) {
+                continue;
+            }
+            let Some(newest) = newest_release(&source.versions(root).await?) else {
+                continue;
+            };
+            if root
+                .version_req()
+                .map_err(CanonError::Protocol)?
+                .matches(&newest)
+            {
+                continue;
+            }
+
+            let widened = widen(root, &newest);
+            rewrites.push((entry.clone(), widened.to_uri()));
+            *root = widened;
+        }
+    }
+
+    let resolution = Resolver::new(existing_lock.as_ref())
+        .upgrade(upgrade)
+        .resolve(&roots, &source)
+        .await?;
+    if !resolution.failures.is_empty() {
+        let failures = resolution
+            .failures
+            .iter()
+            .map(|(uri, e)| format!("\n  - {}: {}", uri, e))
+            .collect::<String>();
+        return Err(CanonError::Command {
+            message: format!("Could not resolve updates:{}", failures),
+        });
+    }
+
+    let before: BTreeMap<String, String> = existing_lock
+        .iter()
+        .flat_map(|lock| &lock.packages)
+        .map(|p| (format!("{}/{}", p.publisher, p.id), p.version.clone()))
+        .collect();
+    let after: BTreeMap<String, String> = resolution
+        .packages
+        .iter()
+        .map(|(key, p)| {
+            (
+                key.clone(),
+                p.dependency.version.clone().unwrap_or_default(),
+            )
+        })
+        .collect();
+
+    for (from, to) in &rewrites {
+        println!(
+            "  {} canon.yml: {} → {}",
+            style("↑").cyan(),
+            from,
+            style(to).cyan()
+        );
+    }
+    let mut changes = 0;
+    for key in before.keys().chain(after.keys()).collect::<BTreeSet<_>>() {
+        match (before.get(key), after.get(key)) {
+            (Some(old), Some(new)) if old != new => {
+                println!(
+                    "  {} {} {} → {}",
+                    style("↑").green(),
+                    key,
+                    old,
+                    style(new).green()
+                );
+            }
+            (None, Some(new)) => println!("  {} {} {}", style("+").green(), key, new),
+            (Some(old), None) => println!("  {} {} {}", style("-").red(), key, old),
+            _ => continue,
+        }
+        changes += 1;
+    }
+
+    if changes == 0 && rewrites.is_empty() {
+        println!(
+            "{} All dependencies are up to date",
+            style("✓").green().bold()
+        );
+        return Ok(());
+    }
+
+    println!();
+    if dry_run {
+        println!("Dry run: canon.yml, canon.lock and .canon/ were left unchanged");
+        return Ok(());
+    }
+
+    let canon_yml = if rewrites.is_empty() {
+        None
+    } else {
+        if let Some(sequence) = spec
+            .content
+            .get_mut("dependencies")
+            .and_then(Value::as_sequence_mut)
+        {
+            for value in sequence.iter_mut() {
+                if let Some((_, to)) = rewrites
+                    .iter()
+                    .find(|(from, _)| value.as_str() == Some(from))
+                {
+                    *value = Value::String(to.clone());
+                }
+            }
+        }
+        Some(serde_yaml::to_string(&spec).map_err(CanonError::Serialization)?)
+    };
+
+    // Pin the new versions, then let install fetch them and fill in their hashes
+    let packages = resolution
+        .packages
+        .values()
+        .map(|package| {
+            let dep = &package.dependency;
+            existing_lock
+                .as_ref()
+                .and_then(|lock| lock.find(dep))
+                .filter(|locked| Some(&locked.version) == dep.version.as_ref())
+                .cloned()
+                .unwrap_or_else(|| LockedPackage {
+                    publisher: dep.publisher.clone(),
+                    id: dep.id.clone(),
+                    version: dep.version.clone().unwrap_or_default(),
+                    registry: location.url().to_string(),
+                    canonical_hash: None,
+                })
+        })
+        .collect();
+    let lock = CanonLock::new(&spec.dependencies(), packages);
+
+    apply_update(
+        &canon_yml_path,
+        canon_yml.as_deref(),
+        &lock_path,
+        &lock,
+        || install_project(config, &canon_dir, false, false, allow_unsigned, None),
+    )
+    .await?;
+
+    // Versions nothing uses any more
+    for (key, old) in &before {
+        if after.get(key) == Some(old) {
+            continue;
+        }
+        let dep = Dependency::parse(&format!("{}@{}", key, old)).map_err(CanonError::Protocol)?;
+        canon_dir.remove_package(&current_dir.join(location.local_path(&dep)?))?;
+    }
+
+    Ok(())
+}
+
+/// Write the updated canon.yml (if it changed) and canon.lock, then install
+///
+/// install reads both files back, so they are written first; if anything
+/// fails their original contents are restored, keeping the old hash pins.
+async fn apply_update<F>(
+    canon_yml_path: &Path,
+    canon_yml: Option<&str>,
+    lock_path: &Path,
+    lock: &CanonLock,
+    install: impl FnOnce() -> F,
+) -> CanonResult<()>
+where
+    F: Future<Output = CanonResult<()>>,
+{
+    let originals = [canon_yml_path, lock_path]
+        .into_iter()
+        .map(|path| match fs::read(path) {
+            Ok(content) => Ok((path, Some(content))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok((path, None)),
+            Err(e) => Err(CanonError::Io(e)),
+        })
+        .collect::<CanonResult<Vec<_>>>()?;
+
+    let result = async {
+        if let Some(canon_yml) = canon_yml {
+            fs::write(canon_yml_path, canon_yml).map_err(CanonError::Io)?;
+        }
+        lock.save(lock_path).map_err(CanonError::Protocol)?;
+        install().await
+    }
+    .await;
+
+    if result.is_err() {
+        for (path, content) in originals {
+            let restored = match content {
+                Some(content) => fs::write(path, content),
+                None => fs::remove_file(path).or_else(|e| match e.kind() {
+                    io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(e),
+                }),
+            };
+            if let Err(e) = restored {
+                eprintln!(
+                    "{} Could not restore {}: {}",
+                    style("⚠").yellow(),
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+    result
+}
+
+/// The highest version that is not a pre-release
+fn newest_release(versions: &[Version]) -> Option<Version> {
+    versions.iter().filter(|v| v.pre.is_empty()).max().cloned()
+}
+
+/// `dep` with its range moved up to admit `newest`, keeping its operator
+///
+/// Exact pins become the new version; `^`/`~` keep their operator; any
+/// other range is replaced by `^newest`.
+fn widen(dep: &Dependency, newest: &Version) -> Dependency {
+    let exact = dep.pinned_version().is_ok();
+    let version_operator = match &dep.version_operator {
+        Some(operator) => Some(operator.clone()),
+        None if exact => None,
+        None => Some(VersionOperator::Caret),
+    };
+
+    Dependency {
+        publisher: dep.publisher.clone(),
+        id: dep.id.clone(),
+        version: Some(newest.to_string()),
+        version_operator,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_widen_keeps_operator() {
+        let newest = Version::new(2, 1, 0);
+        let widened = |uri: &str| widen(&Dependency::parse(uri).unwrap(), &newest).to_uri();
+
+        assert_eq!(widened("acme.com/api@1.0.0"), "acme.com/api@2.1.0");
+        assert_eq!(widened("acme.com/api@^1.2.0"), "acme.com/api@^2.1.0");
+        assert_eq!(widened("acme.com/api@~1.2.0"), "acme.com/api@~2.1.0");
+        assert_eq!(widened("acme.com/api@1.x"), "acme.com/api@^2.1.0");
+    }
+
+    #[tokio::test]
+    async fn test_failed_install_restores_canon_yml_and_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let canon_yml_path = dir.path().join("canon.yml");
+        let lock_path = dir.path().join(CanonLock::FILE_NAME);
+        fs::write(&canon_yml_path, "original canon.yml").unwrap();
+        fs::write(&lock_path, "original canon.lock").unwrap();
+        let lock = CanonLock::new(&["acme.com/api@^2.0.0".to_string()], Vec::new());
+
+        let failed = || async {
+            Err(CanonError::Command {
+                message: "registry unavailable".to_string(),
+            })
+        };
+        let result = apply_update(
+            &canon_yml_path,
+            Some("updated canon.yml"),
+            &lock_path,
+            &lock,
+            failed,
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(
+            fs::read_to_string(&canon_yml_path).unwrap(),
+            "original canon.yml"
+        );
+        assert_eq!(
+            fs::read_to_string(&lock_path).unwrap(),
+            "original canon.lock"
+        );
+
+        // A lock that did not exist before is removed again
+        fs::remove_file(&lock_path).unwrap();
+        let result = apply_update(&canon_yml_path, None, &lock_path, &lock, failed).await;
+        assert!(result.is_err());
+        assert!(!lock_path.exists());
+
+        apply_update(
+            &canon_yml_path,
+            Some("updated canon.yml"),
+            &lock_path,
+            &lock,
+            || async { Ok(()) },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&canon_yml_path).unwrap(),
+            "updated canon.yml"
+        );
+        assert!(lock_path.exists());
+    }
+}
@@ -1,5 +1,9 @@
+use crate::config::Config;
+use crate::core::bundled::bundled_spec;
+use crate::core::http::HttpClient;
+use crate::core::registry::{Registry, RegistryLocation};
 use crate::utils::{CanonError, CanonResult};
-use canon_protocol::{CanonSpecification, Dependency, FieldType, SchemaField};
+use canon_protocol::{parse_version, CanonSpecification, Dependency, FieldType, SchemaField};
 use console::style;
 use serde_yaml::Value;
 use std::collections::HashMap;
@@ -7,6 +11,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 pub async fn run_validate(
+    config: &Config,
     path: Option<String>,
     strict: bool,
     _schema: Option<String>,
@@ -68,7 +73,22 @@ pub async fn run_validate(
         validate_type_definition(&spec, &mut errors, &mut warnings);
     } else {
         // Validate against the type's schema if we can fetch it
-        validate_against_type(&spec, canon_path.parent(), &mut errors, &mut warnings).await;
+        let registry = RegistryLocation::from_config(config)?;
+        // Offline, type definitions come only from .canon/ and the bundled specs
+        let http = if config.offline() {
+            None
+        } else {
+            Some(HttpClient::from_config(config)?)
+        };
+        validate_against_type(
+            &spec,
+            canon_path.parent(),
+            &registry,
+            http.as_ref(),
+            &mut errors,
+            &mut warnings,
+        )
+        .await;
     }
 
     // Report results
@@ -147,10 +167,10 @@ fn validate_metadata(
         ));
     }
 
-    // Validate semantic version format
-    if !is_valid_semver(&spec.metadata.version) {
+    // Validate semantic version format, prereleases and build metadata included
+    if parse_version(&spec.metadata.version).is_err() {
         errors.push(format!(
-            "Invalid metadata.version '{}': must be semantic version (MAJOR.MINOR.PATCH)",
+            "Invalid metadata.version '{}': must be semantic version (MAJOR.MINOR.PATCH[-PRERELEASE][+BUILD])",
             spec.metadata.version
         ));
     }
@@ -261,6 +281,8 @@ fn validate_schema_field(
 async fn validate_against_type(
     spec: &CanonSpecification,
     base_dir: Option<&Path>,
+    registry: &RegistryLocation,
+    http: Option<&HttpClient>,
     errors: &mut Vec<String>,
     warnings: &mut Vec<String>,
 ) {
@@ -271,12 +293,12 @@ async fn validate_against_type(
     };
 
     // Try to load the type definition
-    let type_spec = match load_type_definition(&type_dep, base_dir).await {
+    let type_spec = match load_type_definition(&type_dep, base_dir, registry, http).await {
         Ok(spec) => spec,
-        Err(_) => {
+        Err(e) => {
             warnings.push(format!(
-                "Could not load type definition '{}' for validation",
-                spec.r#type
+                "Could not load type definition '{}' for validation: {}",
+                spec.r#type, e
             ));
             return;
         }
@@ -291,27 +313,41 @@ async fn validate_against_type(
 async fn load_type_definition(
     dep: &Dependency,
     base_dir: Option<&Path>,
+    registry: &RegistryLocation,
+    http: Option<&HttpClient>,
 ) -> CanonResult<CanonSpecification> {
     // First check if it's cached locally
-    let local_path = if let Some(dir) = base_dir {
-        dir.join(dep.local_path()).join("canon.yml")
-    } else {
-        dep.local_path().join("canon.yml")
+    let local_path = registry.local_path(dep)?.join("canon.yml");
+    let local_path = match base_dir {
+        Some(dir) => dir.join(local_path),
+        None => local_path,
     };
 
-    if local_path.exists() {
-        let content = fs::read_to_string(&local_path).map_err(CanonError::Io)?;
-        let spec: CanonSpecification =
-            serde_yaml::from_str(&content).map_err(|e| CanonError::ValidationError {
-                message: format!("Failed to parse type definition: {}", e),
-            })?;
-        return Ok(spec);
-    }
+    let content = if local_path.exists() {
+        fs::read(&local_path).map_err(CanonError::Io)?
+    } else if let Some(http) = http {
+        // Not installed: read it from the registry without caching an unverified copy
+        registry
+            .connect(http)?
+            .fetch(dep, "canon.yml")
+            .await?
+            .ok_or(CanonError::RegistryError {
+                url: registry.package_url(dep)?,
+                status: "not found".to_string(),
+            })?
+    } else if let Some(bundled) = bundled_spec(dep) {
+        bundled.as_bytes().to_vec()
+    } else {
+        return Err(CanonError::Command {
+            message: format!(
+                "{} is not installed and --offline forbids fetching it",
+                dep.to_uri()
+            ),
+        });
+    };
 
-    // If not cached, we could fetch it from the registry
-    // For now, we'll just return an error
-    Err(CanonError::ValidationError {
-        message: format!("Type definition not found locally: {}", dep.to_uri()),
+    serde_yaml::from_slice(&content).map_err(|e| CanonError::ValidationError {
+        message: format!("Failed to parse type definition: {}", e),
     })
 }
 
@@ -472,14 +508,6 @@ fn validate_value_against_field(
     }
 }
 
-fn is_valid_semver(version: &str) -> bool {
-    let parts: Vec<&str> = version.split('.').collect();
-    if parts.len() != 3 {
-        return false;
-    }
-    parts.iter().all(|p| p.parse::<u32>().is_ok())
-}
-
 fn is_valid_publisher(publisher: &str) -> bool {
     // Basic check for domain-like structure
     publisher.contains('.') || publisher == "localhost" || publisher == "example"
@@ -0,0 +1,215 @@
+use crate::config::Config;
+use crate::core::http::HttpClient;
+use crate::core::integrity::{
+    check_manifest, compare_files, verify_package, PublisherKeyring, Verification,
+};
+use crate::core::manifest::package_files;
+use crate::core::store::{list_installed, InstalledPackage};
+use crate::core::Dependency;
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::semver::Version;
+use canon_protocol::{CanonManifest, CanonSignature};
+use console::style;
+use std::fs;
+use std::path::Path;
+
+pub async fn run_verify(
+    config: &Config,
+    uris: Vec<String>,
+    allow_unsigned: bool,
+) -> CanonResult<()> {
+    let current_dir = std::env::current_dir().map_err(|e| CanonError::Command {
+        message: format!("Failed to get current directory: {}", e),
+    })?;
+
+    let filters = uris
+        .iter()
+        .map(|uri| Dependency::parse(uri).map_err(CanonError::Protocol))
+        .collect::<CanonResult<Vec<_>>>()?;
+
+    let installed = list_installed(&current_dir.join(".canon"))?;
+    let packages: Vec<&InstalledPackage> = installed
+        .iter()
+        .filter(|p| filters.is_empty() || filters.iter().any(|f| matches_filter(f, p)))
+        .collect();
+
+    println!(
+        "{} {} installed packages",
+        style("Verifying").cyan().bold(),
+        packages.len()
+    );
+    println!();
+
+    let mut failed = 0;
+
+    // Every requested package must actually be installed
+    for filter in &filters {
+        if !installed.iter().any(|p| matches_filter(filter, p)) {
+            eprintln!("  {} {} - not installed", style("✗").red(), filter.to_uri());
+            failed += 1;
+        }
+    }
+
+    let keyring = PublisherKeyring::new(HttpClient::from_config(config)?);
+    for package in &packages {
+        let label = format!("{} ({})", package.uri(), package.registry);
+        match verify_installed(package, allow_unsigned, &keyring).await {
+            Ok(Verification::Signed { key_id }) => {
+                println!(
+                    "  {} {} {}",
+                    style("✓").green(),
+                    label,
+                    style(format!("signed by {}", key_id)).dim()
+                );
+            }
+            Ok(Verification::Unsigned) => {
+                println!(
+                    "  {} {} {}",
+                    style("✓").green(),
+                    label,
+                    style("(unsigned)").yellow()
+                );
+            }
+            Err(e) => {
+                eprintln!("  {} {} - {}", style("✗").red(), label, e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!();
+    if failed > 0 {
+        println!(
+            "{} {} packages failed verification",
+            style("✗").red().bold(),
+            failed
+        );
+        Err(CanonError::ValidationError {
+            message: format!("{} packages failed verification", failed),
+        })
+    } else {
+        println!("{} All packages verified", style("✓").green().bold());
+        Ok(())
+    }
+}
+
+/// Whether an installed package is selected by a `publisher/id[@range]` filter
+fn matches_filter(filter: &Dependency, package: &InstalledPackage) -> bool {
+    let dep = &package.dependency;
+    if filter.publisher != dep.publisher || filter.id != dep.id {
+        return false;
+    }
+
+    match (
+        filter.version_req(),
+        dep.version.as_deref().map(Version::parse),
+    ) {
+        (Ok(req), Some(Ok(version))) => req.matches(&version),
+        // Unparseable installed versions only match an identical filter string
+        _ => filter.version.is_none() || filter.version == dep.version,
+    }
+}
+
+/// Re-check an installed package's files, manifest and signature
+async fn verify_installed(
+    package: &InstalledPackage,
+    allow_unsigned: bool,
+    keyring: &PublisherKeyring,
+) -> CanonResult<Verification> {
+    let manifest: Option<CanonManifest> = read_yaml(&package.path.join(CanonManifest::FILE_NAME))?;
+    let signature: Option<CanonSignature> =
+        read_yaml(&package.path.join(CanonSignature::FILE_NAME))?;
+    let files = package_files(&package.path)?;
+
+    // Report file-level differences in full before looking at the signature
+    if let Some(manifest) = &manifest {
+        check_manifest(manifest)?;
+        let report = compare_files(manifest, &files);
+        if !report.is_clean() {
+            return Err(CanonError::ValidationError {
+                message: format!("files do not match manifest ({})", report.describe()),
+            });
+        }
+    }
+
+    let keys = match signature {
+        Some(_) => keyring.get(&package.dependency.publisher).await,
+        None => None,
+    };
+
+    verify_package(
+        &files,
+        manifest.as_ref(),
+        signature.as_ref(),
+        keys.as_ref(),
+        allow_unsigned,
+    )
+}
+
+fn read_yaml<T: serde::de::DeserializeOwned>(path: &Path) -> CanonResult<Option<T>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path).map_err(CanonError::Io)?;
+    serde_yaml::from_str(&content)
+        .map(Some)
+        .map_err(|e| CanonError::ValidationError {
+            message: format!("Failed to parse {}: {}", path.display(), e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::manifest::generate_manifest;
+
+    const CANON_YML: &str = "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: 1.0.0\n  publisher: acme.com\n";
+
+    async fn verify(dir: &Path) -> CanonResult<Verification> {
+        let package = InstalledPackage {
+            registry: "localhost".to_string(),
+            dependency: Dependency::parse("acme.com/api@1.0.0").unwrap(),
+            path: dir.to_path_buf(),
+        };
+        let keyring = PublisherKeyring::new(HttpClient::from_config(&Config::defaults())?);
+        verify_installed(&package, true, &keyring).await
+    }
+
+    #[tokio::test]
+    async fn test_verify_installed_reports_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("canon.yml"), CANON_YML).unwrap();
+        fs::create_dir_all(dir.path().join("artifacts")).unwrap();
+        fs::write(dir.path().join("artifacts/README.md"), "# API").unwrap();
+        let manifest = generate_manifest(dir.path()).unwrap();
+        fs::write(
+            dir.path().join(CanonManifest::FILE_NAME),
+            serde_yaml::to_string(&manifest).unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(
+            verify(dir.path()).await,
+            Ok(Verification::Unsigned)
+        ));
+
+        let failure = || async { verify(dir.path()).await.unwrap_err().to_string() };
+
+        fs::write(dir.path().join("artifacts/README.md"), "# Changed").unwrap();
+        assert!(failure().await.contains("modified: artifacts/README.md"));
+        fs::remove_file(dir.path().join("artifacts/README.md")).unwrap();
+        assert!(failure().await.contains("missing: artifacts/README.md"));
+        fs::write(dir.path().join("artifacts/README.md"), "# API").unwrap();
+
+        fs::write(dir.path().join("extra.md"), "x").unwrap();
+        assert!(failure().await.contains("extra: extra.md"));
+        fs::remove_file(dir.path().join("extra.md")).unwrap();
+
+        // Hidden files and .canonignore hide nothing from verification
+        fs::write(dir.path().join(".canonignore"), "*.md\n").unwrap();
+        let err = failure().await;
+        assert!(err.contains("extra: .canonignore"), "{}", err);
+        fs::remove_file(dir.path().join(".canonignore")).unwrap();
+        fs::write(dir.path().join("artifacts/.hidden"), "x").unwrap();
+        assert!(failure().await.contains("extra: artifacts/.hidden"));
+    }
+}
@@ -0,0 +1,44 @@
+use crate::commands::tree::load_graph;
+use crate::config::Config;
+use crate::core::graph::EdgeKind;
+use crate::core::Dependency;
+use crate::utils::{CanonError, CanonResult};
+use console::style;
+
+pub async fn run_why(config: &Config, uri: &str) -> CanonResult<()> {
+    let dep = Dependency::parse(uri).map_err(CanonError::Protocol)?;
+    let graph = load_graph(config).await?;
+
+    let Some(target) = graph.find(&dep) else {
+        return Err(CanonError::Command {
+            message: format!("{} is not in the dependency graph", uri),
+        });
+    };
+    if dep.version.is_some() && dep.version_operator.is_none() && dep.to_uri() != target {
+        return Err(CanonError::Command {
+            message: format!("{} is not in the dependency graph ({} is)", uri, target),
+        });
+    }
+
+    let paths = graph.paths_to(target);
+    println!(
+        "{} is required through {} {}:",
+        style(target).cyan(),
+        paths.len(),
+        if paths.len() == 1 { "path" } else { "paths" }
+    );
+    println!();
+    for path in &paths {
+        let steps = path
+            .iter()
+            .map(|edge| match edge.kind {
+                EdgeKind::Dependency => edge.package.clone(),
+                kind => format!("{} {}", edge.package, style(format!("({})", kind)).dim()),
+            })
+            .collect::<Vec<_>>()
+            .join(" → ");
+        println!("  {} → {}", graph.root, steps);
+    }
+
+    Ok(())
+}
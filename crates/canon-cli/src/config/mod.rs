@@ -1,2 +1,710 @@
-// Configuration management module
-// This will be implemented in future phases
+use crate::utils::{CanonError, CanonResult};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::{Table, Value};
+
+/// Project-level config file, next to canon.yml
+pub const PROJECT_FILE: &str = ".canon.toml";
+
+/// How a config value is written and checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Absolute URL, e.g. `https://canon.canon-protocol.org`
+    Url,
+    /// Whole number of seconds, at least 1
+    Seconds,
+    /// Whole number, 0 or more
+    Count,
+    /// `true` or `false`
+    Bool,
+    /// Filesystem path
+    Path,
+}
+
+/// A known configuration key
+#[derive(Debug)]
+pub struct Key {
+    /// Dotted name, e.g. `registry.default`; also the TOML table path
+    pub name: &'static str,
+    pub kind: Kind,
+    pub default: Option<&'static str>,
+    pub description: &'static str,
+    /// Ignored in a project's `.canon.toml`, so a cloned repository cannot
+    /// choose where canon's traffic goes or which certificates it trusts
+    pub user_only: bool,
+}
+
+/// Every key canon reads from config
+pub const KEYS: &[Key] = &[
+    Key {
+        name: "registry.default",
+        kind: Kind::Url,
+        default: Some("https://canon.canon-protocol.org"),
+        description: "Registry used when --registry is not given",
+        user_only: false,
+    },
+    Key {
+        name: "network.timeout",
+        kind: Kind::Seconds,
+        default: Some("30"),
+        description: "Seconds to wait for a registry response",
+        user_only: false,
+    },
+    Key {
+        name: "network.connect_timeout",
+        kind: Kind::Seconds,
+        default: Some("10"),
+        description: "Seconds to wait when connecting to a registry",
+        user_only: false,
+    },
+    Key {
+        name: "network.retries",
+        kind: Kind::Count,
+        default: Some("3"),
+        description: "Times to retry a request after a connection error, 5xx or 429",
+        user_only: false,
+    },
+    Key {
+        name: "network.proxy",
+        kind: Kind::Url,
+        default: None,
+        description: "Proxy for every registry request, e.g. http://proxy.internal:3128",
+        user_only: true,
+    },
+    Key {
+        name: "network.ca_cert",
+        kind: Kind::Path,
+        default: None,
+        description: "PEM file with an extra CA certificate to trust",
+        user_only: true,
+    },
+    Key {
+        name: "network.offline",
+        kind: Kind::Bool,
+        default: Some("false"),
+        description:
+            "Never use the network; work from .canon/, the package cache and bundled specs",
+        user_only: false,
+    },
+    Key {
+        name: "cache.dir",
+        kind: Kind::Path,
+        default: None,
+        description: "Package cache shared by all projects (default: <cache dir>/canon)",
+        user_only: false,
+    },
+    Key {
+        name: "signing.key",
+        kind: Kind::Path,
+        default: None,
+        description: "Signing key file used by 'canon build --sign'",
+        user_only: false,
+    },
+];
+
+impl Key {
+    /// Environment variable overriding this key, e.g. `CANON_REGISTRY_DEFAULT`
+    pub fn env_var(&self) -> String {
+        format!("CANON_{}", self.name.replace('.', "_").to_ascii_uppercase())
+    }
+
+    /// Parse a value given on the command line or in the environment
+    pub fn parse(&self, raw: &str) -> CanonResult<Value> {
+        let value = match self.kind {
+            Kind::Seconds => {
+                Value::Integer(raw.trim().parse().map_err(|_| CanonError::Config {
+                    message: format!(
+                        "{} must be a whole number of seconds, got '{}'",
+                        self.name, raw
+                    ),
+                })?)
+            }
+            Kind::Count => Value::Integer(raw.trim().parse().map_err(|_| CanonError::Config {
+                message: format!("{} must be a whole number, got '{}'", self.name, raw),
+            })?),
+            Kind::Bool => Value::Boolean(match raw.trim() {
+                "true" | "1" => true,
+                "false" | "0" => false,
+                _ => {
+                    return Err(CanonError::Config {
+                        message: format!("{} must be true or false, got '{}'", self.name, raw),
+                    })
+                }
+            }),
+            Kind::Url | Kind::Path => Value::String(raw.to_string()),
+        };
+        self.check(&value)?;
+        Ok(value)
+    }
+
+    /// Check a value's type and range, whichever layer it came from
+    pub fn check(&self, value: &Value) -> CanonResult<()> {
+        let valid = match (self.kind, value) {
+            (Kind::Seconds, Value::Integer(seconds)) => *seconds >= 1,
+            (Kind::Count, Value::Integer(count)) => *count >= 0,
+            (Kind::Url, Value::String(url)) => url::Url::parse(url).is_ok(),
+            (Kind::Path, Value::String(path)) => !path.is_empty(),
+            (Kind::Bool, Value::Boolean(_)) => true,
+            _ => false,
+        };
+        if valid {
+            return Ok(());
+        }
+
+        let expected = match self.kind {
+            Kind::Url => "an absolute URL such as https://canon.canon-protocol.org",
+            Kind::Seconds => "a whole number of seconds, at least 1",
+            Kind::Count => "a whole number, 0 or more",
+            Kind::Bool => "true or false",
+            Kind::Path => "a file path",
+        };
+        Err(CanonError::Config {
+            message: format!("{} must be {}, got {}", self.name, expected, value),
+        })
+    }
+}
+
+/// Look up a known key by name
+pub fn find_key(name: &str) -> CanonResult<&'static Key> {
+    KEYS.iter()
+        .find(|key| key.name == name)
+        .ok_or_else(|| CanonError::Config {
+            message: format!(
+                "Unknown config key '{}' (known keys: {})",
+                name,
+                KEYS.iter().map(|k| k.name).collect::<Vec<_>>().join(", ")
+            ),
+        })
+}
+
+/// Where a resolved value came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Flag(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Flag(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+/// A resolved value and the layer it came from
+#[derive(Debug, Clone)]
+pub struct Setting {
+    pub value: Value,
+    pub source: Source,
+}
+
+/// Configuration merged from every layer
+///
+/// Later layers win: built-in defaults, the user file
+/// (`<config dir>/canon/config.toml`, or `--config <path>`), the project's
+/// `.canon.toml`, `CANON_*` environment variables, then command-line flags.
+/// The project file cannot set [`Key::user_only`] keys.
+#[derive(Debug, Clone)]
+pub struct Config {
+    user_file: Option<PathBuf>,
+    project_file: PathBuf,
+    settings: BTreeMap<&'static str, Setting>,
+    /// Invalid values skipped by a lenient load, and user-only keys
+    /// ignored in the project file
+    problems: Vec<String>,
+}
+
+impl Config {
+    /// Load configuration for the current directory
+    ///
+    /// `config_file` replaces the user file; `registry` and `offline` are the
+    /// global `--registry` and `--offline` flags.
+    pub fn load(
+        config_file: Option<&Path>,
+        registry: Option<&str>,
+        offline: bool,
+    ) -> CanonResult<Self> {
+        Self::load_with(config_file, registry, offline, false)
+    }
+
+    /// Load like [`Config::load`], but skip invalid values and unreadable
+    /// files instead of failing, listing them in [`Config::problems`]
+    ///
+    /// Used by `canon config` so a bad value can still be inspected and fixed.
+    pub fn load_lenient(
+        config_file: Option<&Path>,
+        registry: Option<&str>,
+        offline: bool,
+    ) -> CanonResult<Self> {
+        Self::load_with(config_file, registry, offline, true)
+    }
+
+    fn load_with(
+        config_file: Option<&Path>,
+        registry: Option<&str>,
+        offline: bool,
+        lenient: bool,
+    ) -> CanonResult<Self> {
+        let user_file = config_file.map(Path::to_path_buf).or_else(user_config_path);
+        let project_file = std::env::current_dir()
+            .map_err(CanonError::Io)?
+            .join(PROJECT_FILE);
+
+        let mut flags = Vec::new();
+        if let Some(registry) = registry {
+            flags.push(("registry.default", "--registry", registry));
+        }
+        if offline {
+            flags.push(("network.offline", "--offline", "true"));
+        }
+
+        Self::resolve(
+            user_file,
+            project_file,
+            |var| std::env::var(var).ok(),
+            &flags,
+            lenient,
+        )
+    }
+
+    /// Built-in defaults only, for tests
+    #[cfg(test)]
+    pub fn defaults() -> Self {
+        Self::resolve(None, PathBuf::from(PROJECT_FILE), |_| None, &[], false).unwrap()
+    }
+
+    fn resolve(
+        user_file: Option<PathBuf>,
+        project_file: PathBuf,
+        env: impl Fn(&str) -> Option<String>,
+        flags: &[(&str, &'static str, &str)],
+        lenient: bool,
+    ) -> CanonResult<Self> {
+        let mut settings = BTreeMap::new();
+        let mut problems = Vec::new();
+        let mut ignored = Vec::new();
+        let mut report = |error: CanonError| -> CanonResult<()> {
+            if !lenient {
+                return Err(error);
+            }
+            problems.push(error_message(error));
+            Ok(())
+        };
+
+        for key in KEYS {
+            if let Some(default) = key.default {
+                settings.insert(
+                    key.name,
+                    Setting {
+                        value: key.parse(default)?,
+                        source: Source::Default,
+                    },
+                );
+            }
+        }
+
+        for path in user_file.iter().chain([&project_file]) {
+            let table = match read_table(path) {
+                Ok(table) => table,
+                Err(e) => {
+                    report(e)?;
+                    continue;
+                }
+            };
+            for key in KEYS {
+                if let Some(value) = lookup(&table, key.name) {
+                    if key.user_only && *path == project_file {
+                        ignored.push(error_message(in_file(path, user_only_error(key))));
+                        continue;
+                    }
+                    if let Err(e) = key.check(value) {
+                        report(in_file(path, e))?;
+                        continue;
+                    }
+                    settings.insert(
+                        key.name,
+                        Setting {
+                            value: value.clone(),
+                            source: Source::File(path.clone()),
+                        },
+                    );
+                }
+            }
+        }
+
+        for key in KEYS {
+            let var = key.env_var();
+            if let Some(raw) = env(&var) {
+                let value = match key.parse(&raw) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        report(e)?;
+                        continue;
+                    }
+                };
+                settings.insert(
+                    key.name,
+                    Setting {
+                        value,
+                        source: Source::Env(var),
+                    },
+                );
+            }
+        }
+
+        for (name, flag, raw) in flags {
+            let key = find_key(name)?;
+            settings.insert(
+                key.name,
+                Setting {
+                    value: key.parse(raw)?,
+                    source: Source::Flag(flag),
+                },
+            );
+        }
+
+        problems.extend(ignored);
+        Ok(Self {
+            user_file,
+            project_file,
+            settings,
+            problems,
+        })
+    }
+
+    /// Resolved value of a key, if set anywhere (including defaults)
+    pub fn get(&self, name: &str) -> Option<&Setting> {
+        self.settings.get(name)
+    }
+
+    /// User-level config file, if one could be located
+    pub fn user_file(&self) -> Option<&Path> {
+        self.user_file.as_deref()
+    }
+
+    /// Values the load skipped, each with its file or setting
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    pub fn project_file(&self) -> &Path {
+        &self.project_file
+    }
+
+    /// Registry used when a command is not given one explicitly
+    pub fn default_registry(&self) -> String {
+        self.string("registry.default").unwrap_or_default()
+    }
+
+    /// Default signing key for `canon build --sign`
+    pub fn signing_key(&self) -> Option<PathBuf> {
+        self.string("signing.key").map(PathBuf::from)
+    }
+
+    /// How long to wait for a registry response
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.integer("network.timeout").unwrap_or(30))
+    }
+
+    /// How long to wait when connecting to a registry
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.integer("network.connect_timeout").unwrap_or(10))
+    }
+
+    /// Times to retry a failed request
+    pub fn retries(&self) -> u32 {
+        self.integer("network.retries")
+            .map_or(3, |n| u32::try_from(n).unwrap_or(u32::MAX))
+    }
+
+    /// Whether network access is disabled
+    pub fn offline(&self) -> bool {
+        matches!(
+            self.get("network.offline").map(|s| &s.value),
+            Some(Value::Boolean(true))
+        )
+    }
+
+    /// Directory of the user-level package cache, if one can be located
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        self.string("cache.dir")
+            .map(PathBuf::from)
+            .or_else(|| dirs::cache_dir().map(|dir| dir.join("canon")))
+    }
+
+    /// Proxy URL for registry requests
+    pub fn proxy(&self) -> Option<String> {
+        self.string("network.proxy")
+    }
+
+    /// Extra CA certificate to trust
+    pub fn ca_cert(&self) -> Option<PathBuf> {
+        self.string("network.ca_cert").map(PathBuf::from)
+    }
+
+    fn integer(&self, name: &str) -> Option<u64> {
+        match &self.get(name)?.value {
+            Value::Integer(n) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+
+    fn string(&self, name: &str) -> Option<String> {
+        match &self.get(name)?.value {
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Default user config file, `<config dir>/canon/config.toml`
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("canon").join("config.toml"))
+}
+
+/// Read a config file as a TOML table; a missing file is empty
+pub fn read_table(path: &Path) -> CanonResult<Table> {
+    if !path.exists() {
+        return Ok(Table::new());
+    }
+    let content = fs::read_to_string(path).map_err(CanonError::Io)?;
+    content.parse().map_err(|e| CanonError::Config {
+        message: format!("Failed to parse {}: {}", path.display(), e),
+    })
+}
+
+/// Check every known key in a config file
+pub fn check_table(path: &Path, table: &Table) -> CanonResult<()> {
+    for key in KEYS {
+        if let Some(value) = lookup(table, key.name) {
+            key.check(value).map_err(|e| in_file(path, e))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_table(path: &Path, table: &Table) -> CanonResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(CanonError::Io)?;
+    }
+    let content = toml::to_string_pretty(table).map_err(|e| CanonError::Config {
+        message: format!("Failed to serialize {}: {}", path.display(), e),
+    })?;
+    fs::write(path, content).map_err(CanonError::Io)
+}
+
+/// Set a dotted key, creating intermediate tables
+pub fn set_value(table: &mut Table, name: &str, value: Value) {
+    let (parents, leaf) = split_key(name);
+    let mut current = table;
+    for part in parents {
+        let entry = current
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+        current = entry.as_table_mut().expect("just made a table");
+    }
+    current.insert(leaf.to_string(), value);
+}
+
+/// Remove a dotted key, dropping tables it leaves empty; false if it was not set
+pub fn remove_value(table: &mut Table, name: &str) -> bool {
+    fn remove(table: &mut Table, parts: &[&str]) -> bool {
+        match parts {
+            [] => false,
+            [leaf] => table.remove(*leaf).is_some(),
+            [head, rest @ ..] => {
+                let Some(Value::Table(child)) = table.get_mut(*head) else {
+                    return false;
+                };
+                let removed = remove(child, rest);
+                if child.is_empty() {
+                    table.remove(*head);
+                }
+                removed
+            }
+        }
+    }
+    remove(table, &name.split('.').collect::<Vec<_>>())
+}
+
+/// Format a value for display, without quotes around strings
+pub fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn lookup<'a>(table: &'a Table, name: &str) -> Option<&'a Value> {
+    let (parents, leaf) = split_key(name);
+    let mut current = table;
+    for part in parents {
+        current = current.get(part)?.as_table()?;
+    }
+    current.get(leaf)
+}
+
+fn split_key(name: &str) -> (Vec<&str>, &str) {
+    let mut parts: Vec<&str> = name.split('.').collect();
+    let leaf = parts.pop().unwrap_or_default();
+    (parts, leaf)
+}
+
+/// Error for a user-only key set in a project's `.canon.toml`
+pub fn user_only_error(key: &Key) -> CanonError {
+    CanonError::Config {
+        message: format!(
+            "{} can only be set in the user config or {}, not in {}",
+            key.name,
+            key.env_var(),
+            PROJECT_FILE
+        ),
+    }
+}
+
+fn in_file(path: &Path, error: CanonError) -> CanonError {
+    CanonError::Config {
+        message: format!("{}: {}", path.display(), error_message(error)),
+    }
+}
+
+fn error_message(error: CanonError) -> String {
+    match error {
+        CanonError::Config { message } => message,
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(config: &Config, name: &str) -> String {
+        display_value(&config.get(name).unwrap().value)
+    }
+
+    #[test]
+    fn test_layer_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("config.toml");
+        let project = dir.path().join(PROJECT_FILE);
+        fs::write(
+            &user,
+            "[registry]\ndefault = \"https://user.example\"\n[network]\ntimeout = 5\n",
+        )
+        .unwrap();
+        fs::write(&project, "[network]\ntimeout = 7\nconnect_timeout = 2\n").unwrap();
+
+        let env = |var: &str| (var == "CANON_NETWORK_CONNECT_TIMEOUT").then(|| "3".to_string());
+        let config = Config::resolve(Some(user.clone()), project.clone(), env, &[], false).unwrap();
+        assert_eq!(value(&config, "registry.default"), "https://user.example");
+        assert_eq!(value(&config, "network.timeout"), "7");
+        assert_eq!(value(&config, "network.connect_timeout"), "3");
+        assert_eq!(
+            config.get("network.timeout").unwrap().source,
+            Source::File(project.clone())
+        );
+        assert!(config.signing_key().is_none());
+        assert_eq!(config.connect_timeout(), Duration::from_secs(3));
+        assert_eq!(config.retries(), 3);
+
+        let flags = [("registry.default", "--registry", "https://flag.example")];
+        let config = Config::resolve(Some(user), project, |_| None, &flags, false).unwrap();
+        assert_eq!(value(&config, "registry.default"), "https://flag.example");
+        assert_eq!(
+            config.get("registry.default").unwrap().source,
+            Source::Flag("--registry")
+        );
+    }
+
+    #[test]
+    fn test_invalid_values_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join(PROJECT_FILE);
+        fs::write(&project, "[network]\ntimeout = \"soon\"\n").unwrap();
+        assert!(Config::resolve(None, project.clone(), |_| None, &[], false).is_err());
+
+        fs::remove_file(&project).unwrap();
+        let env = |var: &str| (var == "CANON_NETWORK_TIMEOUT").then(|| "0".to_string());
+        assert!(Config::resolve(None, project, env, &[], false).is_err());
+        assert!(find_key("network.nope").is_err());
+    }
+
+    #[test]
+    fn test_lenient_load_skips_invalid_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("config.toml");
+        let project = dir.path().join(PROJECT_FILE);
+        fs::write(
+            &user,
+            "[network]
+timeout = 5
+retries = -1
+",
+        )
+        .unwrap();
+        fs::write(&project, "[network\n").unwrap();
+        let env = |var: &str| (var == "CANON_NETWORK_OFFLINE").then(|| "maybe".to_string());
+
+        let config = Config::resolve(Some(user), project, env, &[], true).unwrap();
+        assert_eq!(value(&config, "network.timeout"), "5");
+        assert_eq!(value(&config, "network.retries"), "3");
+        assert!(!config.offline());
+        assert_eq!(config.problems().len(), 3);
+    }
+
+    #[test]
+    fn test_project_file_cannot_set_user_only_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("config.toml");
+        let project = dir.path().join(PROJECT_FILE);
+        fs::write(&user, "[network]\nproxy = \"http://user.example:3128\"\n").unwrap();
+        fs::write(
+            &project,
+            "[network]\nproxy = \"http://attacker.example:3128\"\nca_cert = \"ca.pem\"\ntimeout = 7\n",
+        )
+        .unwrap();
+
+        let config = Config::resolve(Some(user), project.clone(), |_| None, &[], false).unwrap();
+        assert_eq!(config.proxy().as_deref(), Some("http://user.example:3128"));
+        assert!(config.ca_cert().is_none());
+        assert_eq!(value(&config, "network.timeout"), "7");
+        assert_eq!(config.problems().len(), 2);
+        assert!(config.problems()[0].contains("network.proxy can only be set in the user config"));
+
+        // The environment can still set them
+        let env = |var: &str| (var == "CANON_NETWORK_CA_CERT").then(|| "ca.pem".to_string());
+        let config = Config::resolve(None, project, env, &[], false).unwrap();
+        assert_eq!(config.ca_cert(), Some(PathBuf::from("ca.pem")));
+    }
+
+    #[test]
+    fn test_set_and_remove_value() {
+        let mut table = Table::new();
+        set_value(
+            &mut table,
+            "signing.key",
+            Value::String("key.yml".to_string()),
+        );
+        assert_eq!(
+            lookup(&table, "signing.key"),
+            Some(&Value::String("key.yml".to_string()))
+        );
+
+        assert!(remove_value(&mut table, "signing.key"));
+        assert!(!remove_value(&mut table, "signing.key"));
+        assert!(table.is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::core::transform::Template;
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::{hash_contents, CanonSpecification, Dependency};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rendered artifacts keyed by content hash, under `.canon/.cache/build`
+///
+/// Entries are written to a temporary file and renamed into place, so a
+/// concurrent build sees either a complete entry or none. Two builds writing
+/// the same key produce identical content, so the last rename winning is safe.
+pub struct BuildCache {
+    dir: PathBuf,
+    enabled: bool,
+}
+
+impl BuildCache {
+    pub fn new(canon_dir: &Path, enabled: bool) -> Self {
+        Self {
+            dir: canon_dir.join(".cache").join("build"),
+            enabled,
+        }
+    }
+
+    /// Cache key for one template rendered against a given context
+    ///
+    /// Covers the CLI version, the engine and its version, the template's
+    /// artifact path and source, and the hash of everything in the context.
+    pub fn key(template: &Template, source: &str, context_hash: &str) -> String {
+        let material = format!(
+            "canon-cli@{}\n{}\n{}\n{}\n{}",
+            env!("CARGO_PKG_VERSION"),
+            template.engine.cache_id(),
+            template.artifact,
+            context_hash,
+            hash_contents(source.as_bytes())
+        );
+        hash_contents(material.as_bytes())
+            .trim_start_matches("sha256:")
+            .to_string()
+    }
+
+    /// Previously rendered output for `key`, if cached
+    pub fn get(&self, key: &str) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        fs::read_to_string(self.dir.join(key)).ok()
+    }
+
+    /// Store rendered output under `key`
+    pub fn put(&self, key: &str, content: &str) -> CanonResult<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir).map_err(CanonError::Io)?;
+        let temp = self
+            .dir
+            .join(format!(".{}.{}.tmp", key, uuid::Uuid::new_v4()));
+        fs::write(&temp, content).map_err(CanonError::Io)?;
+        fs::rename(&temp, self.dir.join(key)).map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            CanonError::Io(e)
+        })
+    }
+}
+
+/// Hash of the template context: the spec and its resolved dependencies
+///
+/// Uses canonical YAML so the hash does not depend on map ordering.
+pub fn context_hash(
+    spec: &CanonSpecification,
+    dependencies: &[(Dependency, CanonSpecification)],
+) -> CanonResult<String> {
+    let mut material = spec.to_canonical_yaml().map_err(CanonError::Protocol)?;
+    for (dep, dep_spec) in dependencies {
+        material.push_str("\n---\n");
+        material.push_str(&dep.to_uri());
+        material.push('\n');
+        material.push_str(&dep_spec.to_canonical_yaml().map_err(CanonError::Protocol)?);
+    }
+    Ok(hash_contents(material.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::transform::Engine;
+
+    fn template() -> Template {
+        Template {
+            source: PathBuf::from("templates/README.md.hbs"),
+            artifact: "artifacts/README.md".to_string(),
+            engine: Engine::Handlebars,
+        }
+    }
+
+    #[test]
+    fn test_hits_until_source_or_context_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BuildCache::new(dir.path(), true);
+        let key = BuildCache::key(&template(), "# {{name}}", "sha256:ctx");
+        assert_eq!(cache.get(&key), None);
+
+        cache.put(&key, "# api").unwrap();
+        let same = BuildCache::key(&template(), "# {{name}}", "sha256:ctx");
+        assert_eq!(cache.get(&same).as_deref(), Some("# api"));
+
+        let edited = BuildCache::key(&template(), "# {{name}}!", "sha256:ctx");
+        assert_eq!(cache.get(&edited), None);
+        let new_context = BuildCache::key(&template(), "# {{name}}", "sha256:other");
+        assert_eq!(cache.get(&new_context), None);
+
+        let tera = Template {
+            engine: Engine::Tera,
+            ..template()
+        };
+        assert_ne!(BuildCache::key(&tera, "# {{name}}", "sha256:ctx"), key);
+        assert!(Engine::Handlebars.cache_id().starts_with("handlebars@4."));
+    }
+
+    #[test]
+    fn test_disabled_cache_is_bypassed() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = BuildCache::key(&template(), "# {{name}}", "sha256:ctx");
+        BuildCache::new(dir.path(), true)
+            .put(&key, "# old")
+            .unwrap();
+
+        let no_cache = BuildCache::new(dir.path(), false);
+        assert_eq!(no_cache.get(&key), None);
+        no_cache.put(&key, "# new").unwrap();
+        assert_eq!(
+            BuildCache::new(dir.path(), true).get(&key).as_deref(),
+            Some("# old")
+        );
+    }
+}
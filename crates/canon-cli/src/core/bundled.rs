@@ -0,0 +1,41 @@
+use canon_protocol::Dependency;
+
+/// Core specifications shipped inside the binary, so `canon init` and
+/// `canon validate` work without a registry
+const SPECS: &[(&str, &str)] = &[
+    (
+        "canon-protocol.org/project@1.0.0",
+        include_str!("../../specs/canon-protocol.org/project/1.0.0/canon.yml"),
+    ),
+    (
+        "canon-protocol.org/type@1.0.0",
+        include_str!("../../specs/canon-protocol.org/type/1.0.0/canon.yml"),
+    ),
+];
+
+/// The bundled canon.yml for `dep`, if it is one of the core specifications
+pub fn bundled_spec(dep: &Dependency) -> Option<&'static str> {
+    let uri = dep.to_uri();
+    SPECS
+        .iter()
+        .find(|(bundled, _)| *bundled == uri)
+        .map(|(_, content)| *content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use canon_protocol::CanonSpecification;
+
+    #[test]
+    fn test_bundled_specs_match_their_uri() {
+        for (uri, content) in SPECS {
+            let spec: CanonSpecification = serde_yaml::from_str(content).unwrap();
+            let dep = Dependency::parse(uri).unwrap();
+            assert_eq!(spec.metadata.publisher, dep.publisher);
+            assert_eq!(spec.metadata.id, dep.id);
+            assert_eq!(Some(spec.metadata.version), dep.version);
+            assert_eq!(bundled_spec(&dep), Some(*content));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::utils::{CanonError, CanonResult};
+use console::style;
+use std::fs::{self, File, TryLockError};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Lock file inside `.canon/` held while a command writes to it
+const LOCK_FILE: &str = ".lock";
+
+/// Where packages are assembled before being moved into place
+const STAGING_DIR: &str = ".staging";
+
+/// Exclusive hold on a project's `.canon/` directory
+///
+/// Commands that write into `.canon/` take this first, so two `canon`
+/// processes in the same project (an editor plugin and a terminal, say)
+/// run one after the other instead of interleaving writes. The lock is
+/// released when the value is dropped or the process exits.
+pub struct CanonDir {
+    project_dir: PathBuf,
+    lock: File,
+}
+
+/// Shared hold on a project's `.canon/` for commands that only read it
+///
+/// Waits for a writer holding [`CanonDir`] to finish, but lets other
+/// readers in at the same time. Released when dropped.
+pub struct SharedLock {
+    _lock: Option<File>,
+}
+
+impl CanonDir {
+    /// Lock `<project_dir>/.canon`, creating it if needed, and wait for any
+    /// other holder
+    pub fn lock(project_dir: &Path) -> CanonResult<Self> {
+        let path = project_dir.join(".canon");
+        fs::create_dir_all(&path).map_err(CanonError::Io)?;
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path.join(LOCK_FILE))
+            .map_err(CanonError::Io)?;
+
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                eprintln!(
+                    "{} for another canon process to finish with {}",
+                    style("Waiting").yellow().bold(),
+                    path.display()
+                );
+                lock.lock().map_err(CanonError::Io)?;
+            }
+            Err(TryLockError::Error(e)) => return Err(CanonError::Io(e)),
+        }
+
+        // Nobody else is writing, so anything staged is left over from a crash
+        let staging = path.join(STAGING_DIR);
+        if staging.exists() {
+            fs::remove_dir_all(&staging).map_err(CanonError::Io)?;
+        }
+
+        Ok(Self {
+            project_dir: project_dir.to_path_buf(),
+            lock,
+        })
+    }
+
+    /// Take a shared lock on `<project_dir>/.canon`, waiting for any writer
+    ///
+    /// Nothing is created or cleaned up: without a `.canon/` lock file
+    /// there is no writer to wait for.
+    pub fn lock_shared(project_dir: &Path) -> CanonResult<SharedLock> {
+        let path = project_dir.join(".canon");
+        let lock = match File::open(path.join(LOCK_FILE)) {
+            Ok(lock) => lock,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(SharedLock { _lock: None }),
+            Err(e) => return Err(CanonError::Io(e)),
+        };
+
+        match lock.try_lock_shared() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                eprintln!(
+                    "{} for another canon process to finish with {}",
+                    style("Waiting").yellow().bold(),
+                    path.display()
+                );
+                lock.lock_shared().map_err(CanonError::Io)?;
+            }
+            Err(TryLockError::Error(e)) => return Err(CanonError::Io(e)),
+        }
+
+        Ok(SharedLock { _lock: Some(lock) })
+    }
+
+    pub fn project_dir(&self) -> &Path {
+        &self.project_dir
+    }
+
+    fn path(&self) -> PathBuf {
+        self.project_dir.join(".canon")
+    }
+
+    /// Delete `.canon/` entirely
+    ///
+    /// Everything but the lock file is removed while the lock is held; the
+    /// directory itself goes after it is released, and stays if another
+    /// process has opened the lock file in the meantime.
+    pub fn remove(self) -> CanonResult<()> {
+        let path = self.path();
+        for entry in fs::read_dir(&path).map_err(CanonError::Io)? {
+            let entry = entry.map_err(CanonError::Io)?;
+            if entry.file_name() == LOCK_FILE {
+                continue;
+            }
+            if entry.file_type().map_err(CanonError::Io)?.is_dir() {
+                fs::remove_dir_all(entry.path()).map_err(CanonError::Io)?;
+            } else {
+                fs::remove_file(entry.path()).map_err(CanonError::Io)?;
+            }
+        }
+
+        drop(self.lock);
+        let _ = fs::remove_dir_all(&path);
+        Ok(())
+    }
+
+    /// Delete an installed package, along with its id and publisher
+    /// directories once they are empty; `false` if it was not installed
+    pub fn remove_package(&self, dir: &Path) -> CanonResult<bool> {
+        if !dir.exists() {
+            return Ok(false);
+        }
+
+        fs::remove_dir_all(dir).map_err(CanonError::Io)?;
+        for parent in dir.ancestors().skip(1).take(2) {
+            if fs::remove_dir(parent).is_err() {
+                break;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Replace the package directory `dir` in one step
+    ///
+    /// `fill` writes the package into an empty staging directory, which is
+    /// then renamed to `dir`, so `dir` is either absent, the previous
+    /// contents, or the complete new package. If `fill` fails nothing in
+    /// `dir` is touched.
+    pub fn install(
+        &self,
+        dir: &Path,
+        fill: impl FnOnce(&Path) -> CanonResult<()>,
+    ) -> CanonResult<()> {
+        replace_via(&self.path().join(STAGING_DIR), dir, fill)
+    }
+}
+
+/// Replace `dir` in one step like [`CanonDir::install`], for a directory
+/// outside `.canon/`
+///
+/// The staging directory is `.staging` next to `dir`, so the final rename
+/// never crosses filesystems. It is removed again once empty.
+pub fn replace_dir(dir: &Path, fill: impl FnOnce(&Path) -> CanonResult<()>) -> CanonResult<()> {
+    let staging = dir
+        .parent()
+        .ok_or_else(|| CanonError::Command {
+            message: format!("Cannot replace {}: it has no parent", dir.display()),
+        })?
+        .join(STAGING_DIR);
+    let result = replace_via(&staging, dir, fill);
+    let _ = fs::remove_dir(&staging);
+    result
+}
+
+fn replace_via(
+    staging: &Path,
+    dir: &Path,
+    fill: impl FnOnce(&Path) -> CanonResult<()>,
+) -> CanonResult<()> {
+    fs::create_dir_all(staging).map_err(CanonError::Io)?;
+    let id = uuid::Uuid::new_v4();
+    let new = staging.join(id.to_string());
+
+    if let Err(e) = fill(&new) {
+        let _ = fs::remove_dir_all(&new);
+        return Err(e);
+    }
+
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent).map_err(CanonError::Io)?;
+    }
+
+    // A rename cannot replace a directory, so move any old copy aside first
+    let old = staging.join(format!("{}.old", id));
+    let replaced = dir.exists();
+    if replaced {
+        fs::rename(dir, &old).map_err(CanonError::Io)?;
+    }
+    fs::rename(&new, dir).map_err(CanonError::Io)?;
+    if replaced {
+        let _ = fs::remove_dir_all(&old);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_replaces_partial_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let canon_dir = CanonDir::lock(dir.path()).unwrap();
+        let package = dir.path().join(".canon/localhost/acme.com/api/1.0.0");
+        fs::create_dir_all(&package).unwrap();
+        fs::write(package.join("stale.txt"), "partial").unwrap();
+
+        let failed = canon_dir.install(&package, |staging| {
+            fs::create_dir_all(staging).map_err(CanonError::Io)?;
+            fs::write(staging.join("canon.yml"), "new").map_err(CanonError::Io)?;
+            Err(CanonError::Command {
+                message: "interrupted".to_string(),
+            })
+        });
+        assert!(failed.is_err());
+        assert!(package.join("stale.txt").exists());
+
+        canon_dir
+            .install(&package, |staging| {
+                fs::create_dir_all(staging).map_err(CanonError::Io)?;
+                fs::write(staging.join("canon.yml"), "new").map_err(CanonError::Io)
+            })
+            .unwrap();
+        assert!(!package.join("stale.txt").exists());
+        assert_eq!(
+            fs::read_to_string(package.join("canon.yml")).unwrap(),
+            "new"
+        );
+        assert_eq!(
+            fs::read_dir(dir.path().join(".canon/.staging"))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_shared_lock_leaves_canon_dir_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let _none = CanonDir::lock_shared(dir.path()).unwrap();
+        assert!(!dir.path().join(".canon").exists());
+
+        drop(CanonDir::lock(dir.path()).unwrap());
+        let staged = dir.path().join(".canon/.staging/leftover");
+        fs::create_dir_all(&staged).unwrap();
+
+        let _first = CanonDir::lock_shared(dir.path()).unwrap();
+        let _second = CanonDir::lock_shared(dir.path()).unwrap();
+        assert!(staged.exists());
+        let writer = File::open(dir.path().join(".canon").join(LOCK_FILE)).unwrap();
+        assert!(matches!(writer.try_lock(), Err(TryLockError::WouldBlock)));
+    }
+}
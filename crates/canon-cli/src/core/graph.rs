@@ -0,0 +1,290 @@
+use crate::core::registry::RegistryLocation;
+use crate::core::resolver::{package_key, Resolver, SpecSource};
+use crate::core::store::InstalledSource;
+use crate::core::{CanonSpecification, Dependency};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::CanonLock;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// How one package came to require another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    /// Listed under `dependencies` in canon.yml
+    Dependency,
+    /// Listed under `includes`
+    Include,
+    /// Target of a `ref` field in the schema
+    Ref,
+}
+
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Dependency => "dependency",
+            Self::Include => "include",
+            Self::Ref => "ref",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Edge {
+    /// `publisher/id@version` at the other end
+    pub package: String,
+    pub kind: EdgeKind,
+}
+
+/// The resolved dependency graph of a project, as installed in `.canon/`
+///
+/// Every node is a `publisher/id@version`; the project itself is the root.
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    pub root: String,
+    /// What each package requires, the root included
+    pub edges: BTreeMap<String, Vec<Edge>>,
+    /// Packages that are not installed, so what they require is unknown
+    pub missing: BTreeSet<String>,
+}
+
+impl DependencyGraph {
+    /// Resolve the dependencies of the project in `project_dir` from what
+    /// is installed for `location`, preferring versions in canon.lock
+    pub async fn load(project_dir: &Path, location: &RegistryLocation) -> CanonResult<Self> {
+        let canon_yml_path = project_dir.join("canon.yml");
+        if !canon_yml_path.exists() {
+            return Err(CanonError::Command {
+                message: "No canon.yml found. Run 'canon init' first.".to_string(),
+            });
+        }
+
+        let yaml_content = fs::read_to_string(&canon_yml_path).map_err(CanonError::Io)?;
+        let spec: CanonSpecification =
+            serde_yaml::from_str(&yaml_content).map_err(|e| CanonError::Config {
+                message: format!("Failed to parse canon.yml: {}", e),
+            })?;
+        let roots = spec
+            .dependencies()
+            .iter()
+            .map(|d| Dependency::parse(d).map_err(CanonError::Protocol))
+            .collect::<CanonResult<Vec<_>>>()?;
+        let lock = CanonLock::load(&project_dir.join(CanonLock::FILE_NAME))
+            .map_err(CanonError::Protocol)?;
+
+        let source = InstalledSource {
+            base_dir: project_dir,
+            location,
+        };
+        let resolution = Resolver::new(lock.as_ref())
+            .resolve(&roots, &source)
+            .await?;
+
+        let uri = |key: &String| resolution.packages[key].dependency.to_uri();
+        let mut graph = Self {
+            root: format!(
+                "{}/{}@{}",
+                spec.metadata.publisher, spec.metadata.id, spec.metadata.version
+            ),
+            ..Self::default()
+        };
+
+        let direct = resolution
+            .packages
+            .iter()
+            .filter(|(_, package)| package.direct)
+            .map(|(key, _)| Edge {
+                package: uri(key),
+                kind: EdgeKind::Dependency,
+            })
+            .collect();
+        graph.edges.insert(graph.root.clone(), direct);
+
+        for (uri, _) in &resolution.failures {
+            graph.missing.insert(uri.clone());
+        }
+
+        for package in resolution.packages.values() {
+            // Resolution only records what a package requires, so re-read
+            // its includes to tell them apart from refs
+            let includes: BTreeSet<String> = match source.load(&package.dependency).await {
+                Ok(spec) => spec
+                    .includes
+                    .iter()
+                    .flatten()
+                    .filter_map(|include| Dependency::parse(include).ok())
+                    .map(|dep| package_key(&dep))
+                    .collect(),
+                Err(_) => BTreeSet::new(),
+            };
+
+            let requires = package
+                .requires
+                .iter()
+                .map(|key| Edge {
+                    package: uri(key),
+                    kind: if includes.contains(key) {
+                        EdgeKind::Include
+                    } else {
+                        EdgeKind::Ref
+                    },
+                })
+                .collect();
+            graph.edges.insert(package.dependency.to_uri(), requires);
+        }
+
+        Ok(graph)
+    }
+
+    /// The installed `publisher/id@version` for `dep`, matched by publisher and id
+    pub fn find(&self, dep: &Dependency) -> Option<&str> {
+        let prefix = format!("{}@", package_key(dep));
+        self.edges
+            .keys()
+            .filter(|uri| **uri != self.root)
+            .find(|uri| uri.starts_with(&prefix))
+            .map(String::as_str)
+    }
+
+    /// What `uri` requires, or with `invert` what requires it
+    pub fn neighbours(&self, uri: &str, invert: bool) -> Vec<Edge> {
+        if !invert {
+            return self.edges.get(uri).cloned().unwrap_or_default();
+        }
+
+        self.edges
+            .iter()
+            .flat_map(|(from, edges)| {
+                edges.iter().filter(|e| e.package == uri).map(|e| Edge {
+                    package: from.clone(),
+                    kind: e.kind,
+                })
+            })
+            .collect()
+    }
+
+    /// Where an inverted tree starts: packages that require nothing
+    pub fn leaves(&self) -> Vec<String> {
+        self.edges
+            .iter()
+            .filter(|(uri, edges)| edges.is_empty() && **uri != self.root)
+            .map(|(uri, _)| uri.clone())
+            .collect()
+    }
+
+    /// Distance in edges of every package reachable from `starts`
+    pub fn depths(&self, starts: &[String], invert: bool) -> BTreeMap<String, usize> {
+        let mut depths = BTreeMap::new();
+        let mut queue: VecDeque<(String, usize)> =
+            starts.iter().map(|uri| (uri.clone(), 0)).collect();
+        while let Some((uri, depth)) = queue.pop_front() {
+            if depths.contains_key(&uri) {
+                continue;
+            }
+            for edge in self.neighbours(&uri, invert) {
+                queue.push_back((edge.package, depth + 1));
+            }
+            depths.insert(uri, depth);
+        }
+        depths
+    }
+
+    /// Every path from the root to `uri` that visits no package twice
+    ///
+    /// Each path lists the edges taken, so the last one leads to `uri`.
+    pub fn paths_to(&self, uri: &str) -> Vec<Vec<Edge>> {
+        fn walk(
+            graph: &DependencyGraph,
+            at: &str,
+            target: &str,
+            path: &mut Vec<Edge>,
+            paths: &mut Vec<Vec<Edge>>,
+        ) {
+            if at == target {
+                paths.push(path.clone());
+                return;
+            }
+            for edge in graph.neighbours(at, false) {
+                let visited =
+                    edge.package == graph.root || path.iter().any(|e| e.package == edge.package);
+                if !visited {
+                    path.push(edge.clone());
+                    walk(graph, &edge.package, target, path, paths);
+                    path.pop();
+                }
+            }
+        }
+
+        let mut paths = Vec::new();
+        walk(self, &self.root, uri, &mut Vec::new(), &mut paths);
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(package: &str, kind: EdgeKind) -> Edge {
+        Edge {
+            package: package.to_string(),
+            kind,
+        }
+    }
+
+    fn graph() -> DependencyGraph {
+        DependencyGraph {
+            root: "example.com/app@0.1.0".to_string(),
+            edges: BTreeMap::from([
+                (
+                    "example.com/app@0.1.0".to_string(),
+                    vec![
+                        edge("a.org/api@1.0.0", EdgeKind::Dependency),
+                        edge("c.org/util@1.0.0", EdgeKind::Dependency),
+                    ],
+                ),
+                (
+                    "a.org/api@1.0.0".to_string(),
+                    vec![edge("b.org/lib@1.0.0", EdgeKind::Include)],
+                ),
+                (
+                    "b.org/lib@1.0.0".to_string(),
+                    vec![
+                        edge("c.org/util@1.0.0", EdgeKind::Ref),
+                        edge("a.org/api@1.0.0", EdgeKind::Ref),
+                    ],
+                ),
+                ("c.org/util@1.0.0".to_string(), Vec::new()),
+            ]),
+            missing: BTreeSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_paths_to_skip_cycles() {
+        let graph = graph();
+        let paths = graph.paths_to("c.org/util@1.0.0");
+        assert_eq!(
+            paths,
+            vec![
+                vec![
+                    edge("a.org/api@1.0.0", EdgeKind::Dependency),
+                    edge("b.org/lib@1.0.0", EdgeKind::Include),
+                    edge("c.org/util@1.0.0", EdgeKind::Ref),
+                ],
+                vec![edge("c.org/util@1.0.0", EdgeKind::Dependency)],
+            ]
+        );
+
+        assert_eq!(graph.leaves(), vec!["c.org/util@1.0.0"]);
+        let inverted = graph.depths(&graph.leaves(), true);
+        assert_eq!(inverted["example.com/app@0.1.0"], 1);
+        assert_eq!(
+            graph.find(&Dependency::parse("b.org/lib").unwrap()),
+            Some("b.org/lib@1.0.0")
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::config::Config;
+use crate::utils::{CanonError, CanonResult};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::fs;
+use std::time::Duration;
+
+const USER_AGENT: &str = concat!("canon-cli/", env!("CARGO_PKG_VERSION"));
+
+/// Longest wait between attempts, including a server's `Retry-After`
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// HTTP client shared by every registry and publisher key request
+///
+/// Sends a `canon-cli/<version>` user agent, applies the `network.*`
+/// timeouts, proxy and CA settings, and retries transient failures:
+/// connection errors and 5xx responses back off exponentially, and 429
+/// responses wait for `Retry-After`. Requests that are not idempotent are
+/// only retried when they never reached the server or were rate limited.
+/// With `network.offline` set, every request fails without being sent.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    retries: u32,
+    base_delay: Duration,
+    offline: bool,
+}
+
+impl HttpClient {
+    pub fn from_config(config: &Config) -> CanonResult<Self> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(config.timeout())
+            .connect_timeout(config.connect_timeout());
+
+        if let Some(proxy) = config.proxy() {
+            let proxy = reqwest::Proxy::all(&proxy).map_err(|e| CanonError::Config {
+                message: format!("Invalid network.proxy '{}': {}", proxy, e),
+            })?;
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(path) = config.ca_cert() {
+            let pem = fs::read(&path).map_err(|e| CanonError::Config {
+                message: format!("Failed to read CA certificate {}: {}", path.display(), e),
+            })?;
+            let cert = reqwest::Certificate::from_pem(&pem).map_err(|e| CanonError::Config {
+                message: format!("Invalid CA certificate {}: {}", path.display(), e),
+            })?;
+            builder = builder.add_root_certificate(cert);
+        }
+
+        let client = builder.build().map_err(|e| CanonError::Network {
+            message: format!("Failed to create HTTP client: {}", e),
+        })?;
+
+        Ok(Self {
+            client,
+            retries: config.retries(),
+            base_delay: Duration::from_millis(500),
+            offline: config.offline(),
+        })
+    }
+
+    /// GET `url`; `None` on 404, an error for any other failure
+    pub async fn get(&self, url: &str) -> CanonResult<Option<Vec<u8>>> {
+        let response = self.send(url, true, |client| client.get(url)).await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(CanonError::Network {
+                message: format!("Failed to fetch {} (status: {})", url, response.status()),
+            });
+        }
+
+        let bytes = response.bytes().await.map_err(|e| CanonError::Network {
+            message: format!("Failed to read response from {}: {}", url, e),
+        })?;
+        Ok(Some(bytes.to_vec()))
+    }
+
+    /// Send the request `build` makes, retrying transient failures
+    ///
+    /// The final response is returned whatever its status; only a request
+    /// that never got a response is an error.
+    pub async fn send(
+        &self,
+        url: &str,
+        idempotent: bool,
+        build: impl Fn(&reqwest::Client) -> RequestBuilder,
+    ) -> CanonResult<Response> {
+        if self.offline {
+            return Err(CanonError::Network {
+                message: format!("Cannot fetch {} while offline (--offline)", url),
+            });
+        }
+
+        let mut attempt = 0;
+        loop {
+            let result = build(&self.client).send().await;
+
+            let delay = match &result {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    Some(retry_after(response).unwrap_or_else(|| self.backoff(attempt)))
+                }
+                Ok(response) if idempotent && response.status().is_server_error() => {
+                    Some(self.backoff(attempt))
+                }
+                Err(e) if e.is_connect() || (idempotent && e.is_timeout()) => {
+                    Some(self.backoff(attempt))
+                }
+                _ => None,
+            };
+
+            match delay {
+                Some(delay) if attempt < self.retries => {
+                    tokio::time::sleep(delay.min(MAX_DELAY)).await;
+                    attempt += 1;
+                }
+                _ => {
+                    return result.map_err(|e| CanonError::Network {
+                        message: format!("Failed to fetch {}: {}", url, e),
+                    })
+                }
+            }
+        }
+    }
+
+    /// `base_delay`, doubled for each earlier attempt
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_DELAY)
+    }
+}
+
+/// Wait requested by a `Retry-After` header, in seconds or as an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(retries: u32) -> HttpClient {
+        HttpClient {
+            client: reqwest::Client::new(),
+            retries,
+            base_delay: Duration::from_millis(1),
+            offline: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors_then_succeeds() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/spec"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/spec"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/spec", server.uri());
+        assert_eq!(client(3).get(&url).await.unwrap(), Some(b"ok".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/spec", server.uri());
+        assert!(client(1).get(&url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_honors_retry_after_and_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/limited"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/limited"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let http = client(1);
+        let url = format!("{}/limited", server.uri());
+        assert_eq!(http.get(&url).await.unwrap(), Some(b"ok".to_vec()));
+
+        let missing = format!("{}/missing", server.uri());
+        assert_eq!(http.get(&missing).await.unwrap(), None);
+    }
+}
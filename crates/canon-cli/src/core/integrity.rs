@@ -0,0 +1,385 @@
+use crate::core::http::HttpClient;
+use crate::core::{CanonSpecification, Dependency};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::{CanonManifest, CanonSignature, ManifestFile, PublisherKeys};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Differences between a manifest and the files actually present
+#[derive(Debug, Default)]
+pub struct FileReport {
+    /// Listed in the manifest but not present
+    pub missing: Vec<String>,
+    /// Present but not listed in the manifest
+    pub extra: Vec<String>,
+    /// Present with a different hash or size than the manifest records
+    pub modified: Vec<String>,
+}
+
+impl FileReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.modified.is_empty()
+    }
+
+    /// One-line description of every problem, e.g. "modified: canon.yml"
+    pub fn describe(&self) -> String {
+        [
+            ("missing", &self.missing),
+            ("extra", &self.extra),
+            ("modified", &self.modified),
+        ]
+        .iter()
+        .filter(|(_, paths)| !paths.is_empty())
+        .map(|(label, paths)| format!("{}: {}", label, paths.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ")
+    }
+}
+
+/// How a package's authenticity was established
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verification {
+    /// Manifest hashes match and the signature verified against this key
+    Signed { key_id: String },
+    /// Accepted without a verified signature (only with --allow-unsigned)
+    Unsigned,
+}
+
+/// Compare the files actually present against a manifest
+pub fn compare_files(manifest: &CanonManifest, actual: &[ManifestFile]) -> FileReport {
+    let expected: BTreeMap<&str, &ManifestFile> = manifest
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f))
+        .collect();
+    let present: BTreeMap<&str, &ManifestFile> =
+        actual.iter().map(|f| (f.path.as_str(), f)).collect();
+
+    let mut report = FileReport::default();
+    for (path, file) in &expected {
+        match present.get(path) {
+            None => report.missing.push(path.to_string()),
+            Some(found) if found.hash != file.hash || found.size != file.size => {
+                report.modified.push(path.to_string())
+            }
+            Some(_) => {}
+        }
+    }
+    for path in present.keys() {
+        if !expected.contains_key(path) {
+            report.extra.push(path.to_string());
+        }
+    }
+    report
+}
+
+/// Check that a manifest's recorded totals and canonical hash match its file list
+pub fn check_manifest(manifest: &CanonManifest) -> CanonResult<()> {
+    let computed = CanonManifest::compute_canonical_hash(&manifest.files);
+    if computed != manifest.canonical_hash {
+        return Err(integrity_error(format!(
+            "canonical_hash {} does not match manifest files ({})",
+            manifest.canonical_hash, computed
+        )));
+    }
+
+    let total_size: u64 = manifest.files.iter().map(|f| f.size).sum();
+    if manifest.file_count != manifest.files.len() || manifest.total_size != total_size {
+        return Err(integrity_error(
+            "file_count or total_size does not match manifest files".to_string(),
+        ));
+    }
+
+    if let Some(unsafe_path) = manifest.files.iter().find(|f| !is_safe_path(&f.path)) {
+        return Err(integrity_error(format!(
+            "manifest lists unsafe path '{}'",
+            unsafe_path.path
+        )));
+    }
+
+    Ok(())
+}
+
+/// Verify a package: manifest consistency, file hashes, and signature
+///
+/// `keys` is `None` when the publisher's keys could not be obtained. Unless
+/// `allow_unsigned` is set, a package must have a manifest and a signature
+/// that verifies; a signature that is present but invalid is always refused.
+pub fn verify_package(
+    files: &[ManifestFile],
+    manifest: Option<&CanonManifest>,
+    signature: Option<&CanonSignature>,
+    keys: Option<&PublisherKeys>,
+    allow_unsigned: bool,
+) -> CanonResult<Verification> {
+    let Some(manifest) = manifest else {
+        return if allow_unsigned {
+            Ok(Verification::Unsigned)
+        } else {
+            Err(integrity_error(format!(
+                "package has no {} (use --allow-unsigned for legacy specs)",
+                CanonManifest::FILE_NAME
+            )))
+        };
+    };
+
+    check_manifest(manifest)?;
+
+    let report = compare_files(manifest, files);
+    if !report.is_clean() {
+        return Err(integrity_error(format!(
+            "files do not match manifest ({})",
+            report.describe()
+        )));
+    }
+
+    match (signature, keys) {
+        (Some(signature), Some(keys)) => {
+            signature
+                .verify(manifest, keys)
+                .map_err(CanonError::Protocol)?;
+            Ok(Verification::Signed {
+                key_id: signature.signature.key_id.clone(),
+            })
+        }
+        _ if allow_unsigned => Ok(Verification::Unsigned),
+        (Some(_), None) => Err(integrity_error(
+            "publisher keys unavailable, cannot verify signature (use --allow-unsigned to skip)"
+                .to_string(),
+        )),
+        (None, _) => Err(integrity_error(format!(
+            "package has no {} (use --allow-unsigned for legacy specs)",
+            CanonSignature::FILE_NAME
+        ))),
+    }
+}
+
+/// Check that a downloaded package is the one that was asked for
+///
+/// Neither the canonical hash nor the signature covers the package's
+/// coordinates, so a registry could otherwise serve another validly signed
+/// package, or another version of this one, in its place.
+pub fn check_coordinates(
+    dep: &Dependency,
+    manifest: Option<&CanonManifest>,
+    canon_yml: &[u8],
+) -> CanonResult<()> {
+    let spec: CanonSpecification = serde_yaml::from_slice(canon_yml)
+        .map_err(|e| integrity_error(format!("failed to parse canon.yml: {}", e)))?;
+
+    let mut described = vec![(
+        "canon.yml",
+        &spec.metadata.publisher,
+        &spec.metadata.id,
+        &spec.metadata.version,
+    )];
+    if let Some(manifest) = manifest {
+        let spec = &manifest.specification;
+        described.push((
+            CanonManifest::FILE_NAME,
+            &spec.publisher,
+            &spec.id,
+            &spec.version,
+        ));
+    }
+
+    for (file, publisher, id, version) in described {
+        if *publisher != dep.publisher
+            || *id != dep.id
+            || dep.version.as_ref().is_some_and(|v| v != version)
+        {
+            return Err(integrity_error(format!(
+                "{} describes {}/{}@{}, not {}",
+                file,
+                publisher,
+                id,
+                version,
+                dep.to_uri()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Publisher keys, fetched from each publisher's domain at most once
+///
+/// Safe to share between concurrent installs; a second request for the same
+/// publisher waits for the first fetch instead of repeating it.
+pub struct PublisherKeyring {
+    http: HttpClient,
+    keys: Mutex<HashMap<String, Arc<OnceCell<Option<PublisherKeys>>>>>,
+}
+
+impl PublisherKeyring {
+    pub fn new(http: HttpClient) -> Self {
+        Self {
+            http,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A keyring that already holds `keys` for `publisher`, for tests
+    #[cfg(test)]
+    pub fn with_keys(http: HttpClient, publisher: &str, keys: PublisherKeys) -> Self {
+        let keyring = Self::new(http);
+        keyring.keys.lock().unwrap().insert(
+            publisher.to_string(),
+            Arc::new(OnceCell::new_with(Some(Some(keys)))),
+        );
+        keyring
+    }
+
+    /// Keys published by `publisher`, or `None` if they could not be fetched
+    pub async fn get(&self, publisher: &str) -> Option<PublisherKeys> {
+        let cell = self
+            .keys
+            .lock()
+            .unwrap()
+            .entry(publisher.to_string())
+            .or_default()
+            .clone();
+
+        cell.get_or_init(|| async {
+            self.http
+                .get(&PublisherKeys::url_for(publisher))
+                .await
+                .ok()
+                .flatten()
+                .and_then(|content| serde_yaml::from_slice(&content).ok())
+        })
+        .await
+        .clone()
+    }
+}
+
+/// A relative path that cannot escape the package directory
+pub fn is_safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn integrity_error(message: String) -> CanonError {
+    CanonError::ValidationError {
+        message: format!("Integrity check failed: {}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use canon_protocol::{public_key_to_base64, ManifestSpecification, PublisherKey};
+    use chrono::{Duration, Utc};
+    use ed25519_dalek::SigningKey;
+
+    fn files() -> Vec<ManifestFile> {
+        vec![
+            ManifestFile::from_contents("canon.yml", b"canon: \"1.0\"\n"),
+            ManifestFile::from_contents("artifacts/README.md", b"# API"),
+        ]
+    }
+
+    fn manifest(files: Vec<ManifestFile>) -> CanonManifest {
+        CanonManifest::new(
+            ManifestSpecification {
+                id: "api".to_string(),
+                version: "1.0.0".to_string(),
+                publisher: "acme.com".to_string(),
+                r#type: "canon-protocol.org/type@1.0.0".to_string(),
+            },
+            files,
+        )
+    }
+
+    fn keys(signing_key: &SigningKey) -> PublisherKeys {
+        PublisherKeys {
+            version: "1.0".to_string(),
+            keys: HashMap::from([(
+                "key-1".to_string(),
+                PublisherKey {
+                    algorithm: "ed25519".to_string(),
+                    public_key: public_key_to_base64(signing_key),
+                    created_at: Utc::now() - Duration::days(1),
+                    expires_at: None,
+                    revoked: false,
+                    usage: "signing".to_string(),
+                },
+            )]),
+            revoked_keys: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_verify_package_signatures() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let keys = keys(&signing_key);
+        let manifest = manifest(files());
+        let signature = CanonSignature::sign(&manifest, "key-1", &signing_key);
+
+        let signed = verify_package(
+            &files(),
+            Some(&manifest),
+            Some(&signature),
+            Some(&keys),
+            false,
+        );
+        assert!(matches!(signed, Ok(Verification::Signed { key_id }) if key_id == "key-1"));
+
+        // Unsigned packages need allow_unsigned, with or without a manifest
+        assert!(verify_package(&files(), None, None, None, false).is_err());
+        assert!(verify_package(&files(), Some(&manifest), None, Some(&keys), false).is_err());
+        assert!(matches!(
+            verify_package(&files(), None, None, None, true),
+            Ok(Verification::Unsigned)
+        ));
+        assert!(matches!(
+            verify_package(&files(), Some(&manifest), None, None, true),
+            Ok(Verification::Unsigned)
+        ));
+
+        // A bad signature is refused even when unsigned packages are allowed
+        let other = SigningKey::from_bytes(&[8u8; 32]);
+        let forged = CanonSignature::sign(&manifest, "key-1", &other);
+        assert!(
+            verify_package(&files(), Some(&manifest), Some(&forged), Some(&keys), true).is_err()
+        );
+
+        // Without the publisher's keys a signature cannot be checked
+        let err = verify_package(&files(), Some(&manifest), Some(&signature), None, false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("publisher keys unavailable"), "{}", err);
+    }
+
+    #[test]
+    fn test_verify_package_integrity() {
+        let manifest = manifest(files());
+
+        let mut changed = files();
+        changed[1] = ManifestFile::from_contents("artifacts/README.md", b"# Changed");
+        let err = verify_package(&changed, Some(&manifest), None, None, true)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("modified: artifacts/README.md"), "{}", err);
+
+        let mut tampered = manifest.clone();
+        tampered.files[0].hash = "sha256:0".to_string();
+        let err = check_manifest(&tampered).unwrap_err().to_string();
+        assert!(err.contains("canonical_hash"), "{}", err);
+
+        let escaping = self::manifest(vec![ManifestFile::from_contents("../evil", b"x")]);
+        let err = verify_package(&[], Some(&escaping), None, None, true)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unsafe path '../evil'"), "{}", err);
+
+        assert!(is_safe_path("artifacts/README.md"));
+        for path in ["", "/etc/passwd", "../x", "a/../../x", "./a"] {
+            assert!(!is_safe_path(path), "{}", path);
+        }
+    }
+}
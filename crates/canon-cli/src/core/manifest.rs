@@ -0,0 +1,194 @@
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::{
+    CanonManifest, CanonSignature, CanonSpecification, ManifestFile, ManifestSpecification,
+};
+use ignore::WalkBuilder;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Ignore file honored when scanning a package, using .gitignore syntax
+pub const CANONIGNORE: &str = ".canonignore";
+
+/// Hash every file in a package directory
+///
+/// Hidden files and directories (including `.canon/` and `.git/`) are
+/// skipped, as is anything matched by a `.canonignore`. The manifest and
+/// signature at the package root are never included. Returns the files
+/// sorted by path and the directories that contain them.
+pub fn scan_package(package_dir: &Path) -> CanonResult<(Vec<ManifestFile>, Vec<String>)> {
+    let walker = WalkBuilder::new(package_dir)
+        .hidden(true)
+        .parents(false)
+        .ignore(false)
+        .git_ignore(false)
+        .git_global(false)
+        .git_exclude(false)
+        .add_custom_ignore_filename(CANONIGNORE)
+        .build();
+
+    let mut files = Vec::new();
+    let mut directories = BTreeSet::new();
+
+    for entry in walker {
+        let entry = entry.map_err(|e| CanonError::Command {
+            message: format!("Failed to scan {}: {}", package_dir.display(), e),
+        })?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+
+        let relative = entry
+            .path()
+            .strip_prefix(package_dir)
+            .map_err(|e| CanonError::Command {
+                message: format!("Failed to scan {}: {}", package_dir.display(), e),
+            })?;
+        let path = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if path == CanonManifest::FILE_NAME || path == CanonSignature::FILE_NAME {
+            continue;
+        }
+
+        if let Some((dir, _)) = path.rsplit_once('/') {
+            // Record every ancestor so nested directories are all listed
+            let mut prefix = String::new();
+            for part in dir.split('/') {
+                if !prefix.is_empty() {
+                    prefix.push('/');
+                }
+                prefix.push_str(part);
+                directories.insert(prefix.clone());
+            }
+        }
+
+        let contents = fs::read(entry.path()).map_err(CanonError::Io)?;
+        files.push(ManifestFile::from_contents(&path, &contents));
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok((files, directories.into_iter().collect()))
+}
+
+/// Hash every file under an installed package directory, unfiltered
+///
+/// Unlike [`scan_package`], hidden files and `.canonignore` are not
+/// special: anything on disk that the manifest does not list shows up as
+/// extra. Only the manifest and signature at the root are left out.
+pub fn package_files(package_dir: &Path) -> CanonResult<Vec<ManifestFile>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(package_dir).min_depth(1) {
+        let entry = entry.map_err(|e| CanonError::Command {
+            message: format!("Failed to scan {}: {}", package_dir.display(), e),
+        })?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        let relative = entry
+            .path()
+            .strip_prefix(package_dir)
+            .map_err(|e| CanonError::Command {
+                message: format!("Failed to scan {}: {}", package_dir.display(), e),
+            })?;
+        let path = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if path == CanonManifest::FILE_NAME || path == CanonSignature::FILE_NAME {
+            continue;
+        }
+
+        let contents = fs::read(entry.path()).map_err(CanonError::Io)?;
+        files.push(ManifestFile::from_contents(&path, &contents));
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Build the manifest for a package directory containing a canon.yml
+pub fn generate_manifest(package_dir: &Path) -> CanonResult<CanonManifest> {
+    let canon_yml = package_dir.join("canon.yml");
+    if !canon_yml.exists() {
+        return Err(CanonError::Command {
+            message: format!("No canon.yml found in {}", package_dir.display()),
+        });
+    }
+
+    let content = fs::read_to_string(&canon_yml).map_err(CanonError::Io)?;
+    let spec: CanonSpecification =
+        serde_yaml::from_str(&content).map_err(|e| CanonError::Config {
+            message: format!("Failed to parse {}: {}", canon_yml.display(), e),
+        })?;
+
+    let (files, directories) = scan_package(package_dir)?;
+
+    let mut manifest = CanonManifest::new(
+        ManifestSpecification {
+            id: spec.metadata.id,
+            version: spec.metadata.version,
+            publisher: spec.metadata.publisher,
+            r#type: spec.r#type,
+        },
+        files,
+    );
+    if !directories.is_empty() {
+        manifest.directories = Some(directories);
+    }
+
+    Ok(manifest)
+}
+
+/// Write `canon-manifest.yml` into the package directory
+pub fn write_manifest(package_dir: &Path, manifest: &CanonManifest) -> CanonResult<()> {
+    let yaml = serde_yaml::to_string(manifest).map_err(CanonError::Serialization)?;
+    fs::write(package_dir.join(CanonManifest::FILE_NAME), yaml).map_err(CanonError::Io)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, path: &str, content: &str) {
+        let file = dir.join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, content).unwrap();
+    }
+
+    #[test]
+    fn test_scan_package_skips_hidden_ignored_and_own_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "canon.yml", "canon: \"1.0\"\n");
+        write(dir.path(), "docs/guide/intro.md", "# Intro");
+        write(dir.path(), "docs/draft.md", "wip");
+        write(dir.path(), "build/out.txt", "generated");
+        write(dir.path(), ".secret", "token");
+        write(dir.path(), ".git/config", "[core]");
+        write(dir.path(), "docs/.notes", "todo");
+        write(dir.path(), CANONIGNORE, "build/\ndraft.md\n");
+        write(dir.path(), CanonManifest::FILE_NAME, "old manifest");
+        write(dir.path(), CanonSignature::FILE_NAME, "old signature");
+        // Only the root copies are the package's own files
+        write(dir.path(), "docs/canon-manifest.yml", "listed");
+
+        let (files, directories) = scan_package(dir.path()).unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "canon.yml",
+                "docs/canon-manifest.yml",
+                "docs/guide/intro.md"
+            ]
+        );
+        assert_eq!(directories, vec!["docs", "docs/guide"]);
+    }
+}
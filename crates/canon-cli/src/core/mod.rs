@@ -1,2 +1,15 @@
+pub mod build_cache;
+pub mod bundled;
+pub mod canon_dir;
+pub mod graph;
+pub mod http;
+pub mod integrity;
+pub mod manifest;
+pub mod package_cache;
+pub mod registry;
+pub mod resolver;
+pub mod store;
+pub mod transform;
+
 // Re-export types from canon-protocol library
 pub use canon_protocol::{CanonSpecification, Dependency};
@@ -0,0 +1,455 @@
+use crate::config::Config;
+use crate::core::integrity::{check_manifest, Verification};
+use crate::core::manifest::scan_package;
+use crate::core::registry::{DownloadedPackage, RegistryLocation};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::semver::Version;
+use canon_protocol::{
+    compare_versions, CanonManifest, CanonSignature, Dependency, ManifestFile, PublisherKeys,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Verified packages shared by every project of the current user
+///
+/// Entries live at `<root>/<registry>/<publisher>/<id>/<version>/<hash>/`,
+/// where `<hash>` is the canonical hash of the package files (the manifest's
+/// `canonical_hash` when it has one). Next to each entry, `<hash>.yml`
+/// records how the package was verified and when it was last used.
+///
+/// Only packages that passed verification are stored. Projects are populated
+/// by hardlink where possible, so each entry is re-hashed before use: a file
+/// edited through a project's `.canon/` makes the entry a miss rather than
+/// spreading the change.
+pub struct PackageCache {
+    root: PathBuf,
+}
+
+/// When and how a cached package was verified
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryInfo {
+    /// Key that signed the package, `None` if it was accepted unsigned
+    pub key_id: Option<String>,
+    pub last_used: DateTime<Utc>,
+}
+
+/// One package in the cache
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// Registry directory name, as under `.canon/`
+    pub registry: String,
+    pub dependency: Dependency,
+    /// Canonical hash, without the `sha256:` prefix
+    pub hash: String,
+    pub path: PathBuf,
+    pub info: EntryInfo,
+}
+
+impl PackageCache {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// The cache under `cache.dir`, or `<cache dir>/canon` by default
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config
+            .cache_dir()
+            .map(|dir| Self::new(dir.join("packages")))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn version_dir(&self, location: &RegistryLocation, dep: &Dependency) -> CanonResult<PathBuf> {
+        Ok(self
+            .root
+            .join(location.dir_name())
+            .join(&dep.publisher)
+            .join(&dep.id)
+            .join(dep.pinned_version()?.to_string()))
+    }
+
+    /// A usable cached copy of `dep`
+    ///
+    /// With `hash` (from canon.lock) only that entry qualifies; otherwise the
+    /// most recently used one does. Entries whose files no longer match their
+    /// hash are ignored.
+    pub fn find(
+        &self,
+        location: &RegistryLocation,
+        dep: &Dependency,
+        hash: Option<&str>,
+    ) -> CanonResult<Option<CacheEntry>> {
+        let dir = self.version_dir(location, dep)?;
+        let mut entries = read_entries(&dir, location.dir_name(), dep)?;
+        if let Some(hash) = hash {
+            let hash = hash.trim_start_matches("sha256:");
+            entries.retain(|entry| entry.hash == hash);
+        }
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.info.last_used));
+
+        Ok(entries.into_iter().find(|entry| entry.check().is_ok()))
+    }
+
+    /// Versions of `dep`'s package with at least one usable entry
+    pub fn versions(&self, location: &RegistryLocation, dep: &Dependency) -> Vec<Version> {
+        let dir = self
+            .root
+            .join(location.dir_name())
+            .join(&dep.publisher)
+            .join(&dep.id);
+        subdirectories(&dir)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|path| Version::parse(&file_name(&path)).ok())
+            .filter(|version| {
+                let pinned = Dependency {
+                    version: Some(version.to_string()),
+                    version_operator: None,
+                    ..dep.clone()
+                };
+                matches!(self.find(location, &pinned, None), Ok(Some(_)))
+            })
+            .collect()
+    }
+
+    /// Store a verified package; an existing entry with the same hash is kept
+    pub fn store(
+        &self,
+        location: &RegistryLocation,
+        dep: &Dependency,
+        package: &DownloadedPackage,
+        verification: &Verification,
+    ) -> CanonResult<()> {
+        let files: Vec<ManifestFile> = package
+            .files
+            .iter()
+            .map(|(path, content)| ManifestFile::from_contents(path, content))
+            .collect();
+        let hash = content_hash(&files);
+
+        let dir = self.version_dir(location, dep)?;
+        let target = dir.join(&hash);
+        if target.exists() {
+            return Ok(());
+        }
+
+        // Stage and rename, so concurrent installs never see half an entry
+        fs::create_dir_all(&dir).map_err(CanonError::Io)?;
+        let staging = dir.join(format!(".{}.{}.tmp", hash, uuid::Uuid::new_v4()));
+        let result = (|| {
+            for (path, content) in package.all_files() {
+                let file = staging.join(path);
+                if let Some(parent) = file.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(file, content)?;
+            }
+            fs::rename(&staging, &target)
+        })();
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&staging);
+            // Another install stored the same entry first
+            if target.exists() {
+                return Ok(());
+            }
+            return Err(CanonError::Io(e));
+        }
+
+        let info = EntryInfo {
+            key_id: match verification {
+                Verification::Signed { key_id } => Some(key_id.clone()),
+                Verification::Unsigned => None,
+            },
+            last_used: Utc::now(),
+        };
+        write_info(&dir.join(format!("{}.yml", hash)), &info)
+    }
+
+    /// Every entry, sorted by registry, publisher, id, version (by SemVer
+    /// precedence) and hash
+    pub fn list(&self) -> CanonResult<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        for registry in subdirectories(&self.root)? {
+            let registry_name = file_name(&registry);
+            for publisher in subdirectories(&registry)? {
+                for id in subdirectories(&publisher)? {
+                    let mut versions = subdirectories(&id)?;
+                    versions.sort_by(|a, b| compare_versions(&file_name(a), &file_name(b)));
+                    for version in versions {
+                        let dep = Dependency {
+                            publisher: file_name(&publisher),
+                            id: file_name(&id),
+                            version: Some(file_name(&version)),
+                            version_operator: None,
+                        };
+                        entries.extend(read_entries(&version, &registry_name, &dep)?);
+                    }
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Remove leftovers of interrupted stores
+    pub fn remove_staging(&self) -> CanonResult<usize> {
+        fn visit(dir: &Path, depth: usize, removed: &mut usize) -> std::io::Result<()> {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if !path.is_dir() {
+                    continue;
+                }
+                if depth == 4 {
+                    if file_name(&path).ends_with(".tmp") {
+                        fs::remove_dir_all(&path)?;
+                        *removed += 1;
+                    }
+                } else {
+                    visit(&path, depth + 1, removed)?;
+                }
+            }
+            Ok(())
+        }
+
+        let mut removed = 0;
+        if self.root.exists() {
+            visit(&self.root, 0, &mut removed).map_err(CanonError::Io)?;
+        }
+        Ok(removed)
+    }
+}
+
+impl CacheEntry {
+    /// `publisher/id@version`
+    pub fn uri(&self) -> String {
+        self.dependency.to_uri()
+    }
+
+    pub fn verification(&self) -> Verification {
+        match &self.info.key_id {
+            Some(key_id) => Verification::Signed {
+                key_id: key_id.clone(),
+            },
+            None => Verification::Unsigned,
+        }
+    }
+
+    /// Re-hash the entry's files and check them against its hash and manifest
+    pub fn check(&self) -> CanonResult<()> {
+        let (files, _) = scan_package(&self.path)?;
+        let actual = content_hash(&files);
+        if actual != self.hash {
+            return Err(CanonError::ValidationError {
+                message: format!("files hash to {} instead of {}", actual, self.hash),
+            });
+        }
+
+        let manifest_path = self.path.join(CanonManifest::FILE_NAME);
+        if manifest_path.exists() {
+            let content = fs::read(&manifest_path).map_err(CanonError::Io)?;
+            let manifest: CanonManifest =
+                serde_yaml::from_slice(&content).map_err(|e| CanonError::ValidationError {
+                    message: format!("Failed to parse {}: {}", CanonManifest::FILE_NAME, e),
+                })?;
+            check_manifest(&manifest)?;
+            if manifest.canonical_hash.trim_start_matches("sha256:") != self.hash {
+                return Err(CanonError::ValidationError {
+                    message: format!("{} does not describe these files", CanonManifest::FILE_NAME),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the entry's signature against the publisher's current keys
+    ///
+    /// The key recorded when the entry was stored may since have been
+    /// revoked, rotated out or expired.
+    pub fn verify_signature(&self, keys: &PublisherKeys) -> CanonResult<()> {
+        let read = |name: &str| -> CanonResult<Vec<u8>> {
+            fs::read(self.path.join(name)).map_err(CanonError::Io)
+        };
+        let manifest: CanonManifest = serde_yaml::from_slice(&read(CanonManifest::FILE_NAME)?)
+            .map_err(|e| CanonError::ValidationError {
+                message: format!("Failed to parse {}: {}", CanonManifest::FILE_NAME, e),
+            })?;
+        let signature: CanonSignature = serde_yaml::from_slice(&read(CanonSignature::FILE_NAME)?)
+            .map_err(|e| CanonError::ValidationError {
+            message: format!("Failed to parse {}: {}", CanonSignature::FILE_NAME, e),
+        })?;
+
+        if self.info.key_id.as_ref() != Some(&signature.signature.key_id) {
+            return Err(CanonError::ValidationError {
+                message: format!(
+                    "{} is not signed with the recorded key",
+                    CanonSignature::FILE_NAME
+                ),
+            });
+        }
+        signature
+            .verify(&manifest, keys)
+            .map_err(CanonError::Protocol)
+    }
+
+    /// Populate `dir` with the entry's files, hardlinked where the
+    /// filesystem allows and copied otherwise, and record the use
+    pub fn link_into(&self, dir: &Path) -> CanonResult<()> {
+        fn visit(from: &Path, to: &Path) -> std::io::Result<()> {
+            fs::create_dir_all(to)?;
+            for entry in fs::read_dir(from)? {
+                let entry = entry?;
+                let target = to.join(entry.file_name());
+                if entry.file_type()?.is_dir() {
+                    visit(&entry.path(), &target)?;
+                } else if fs::hard_link(entry.path(), &target).is_err() {
+                    fs::copy(entry.path(), &target)?;
+                }
+            }
+            Ok(())
+        }
+
+        visit(&self.path, dir).map_err(CanonError::Io)?;
+
+        let mut info = self.info.clone();
+        info.last_used = Utc::now();
+        // Losing the timestamp only affects pruning, so it is not an error
+        let _ = write_info(&self.info_path(), &info);
+        Ok(())
+    }
+
+    /// Total size of the entry's files in bytes
+    pub fn size(&self) -> u64 {
+        walkdir::WalkDir::new(&self.path)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    pub fn remove(&self) -> CanonResult<()> {
+        fs::remove_dir_all(&self.path).map_err(CanonError::Io)?;
+        let info = self.info_path();
+        if info.exists() {
+            fs::remove_file(info).map_err(CanonError::Io)?;
+        }
+        Ok(())
+    }
+
+    fn info_path(&self) -> PathBuf {
+        self.path.with_file_name(format!("{}.yml", self.hash))
+    }
+}
+
+/// Canonical hash of a package's files, without the `sha256:` prefix
+fn content_hash(files: &[ManifestFile]) -> String {
+    CanonManifest::compute_canonical_hash(files)
+        .trim_start_matches("sha256:")
+        .to_string()
+}
+
+/// Entries stored under one version directory
+fn read_entries(dir: &Path, registry: &str, dep: &Dependency) -> CanonResult<Vec<CacheEntry>> {
+    let mut entries = Vec::new();
+    for path in subdirectories(dir)? {
+        let hash = file_name(&path);
+        let Some(info) = read_info(&dir.join(format!("{}.yml", hash))) else {
+            // Written by a store that did not finish
+            continue;
+        };
+        entries.push(CacheEntry {
+            registry: registry.to_string(),
+            dependency: dep.clone(),
+            hash,
+            path,
+            info,
+        });
+    }
+    Ok(entries)
+}
+
+fn read_info(path: &Path) -> Option<EntryInfo> {
+    let content = fs::read(path).ok()?;
+    serde_yaml::from_slice(&content).ok()
+}
+
+fn write_info(path: &Path, info: &EntryInfo) -> CanonResult<()> {
+    let content = serde_yaml::to_string(info).map_err(CanonError::Serialization)?;
+    let temp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    fs::write(&temp, content).map_err(CanonError::Io)?;
+    fs::rename(&temp, path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        CanonError::Io(e)
+    })
+}
+
+/// Non-hidden subdirectories of `dir`, sorted by name; none if it is missing
+fn subdirectories(dir: &Path) -> CanonResult<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir).map_err(CanonError::Io)? {
+        let entry = entry.map_err(CanonError::Io)?;
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && entry.file_type().map_err(CanonError::Io)?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::registry::memory::unsigned_package;
+
+    const CANON_YML: &str = "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: 1.0.0\n  publisher: acme.com\n";
+
+    #[test]
+    fn test_store_find_and_link() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = PackageCache::new(root.path().to_path_buf());
+        let location = RegistryLocation::parse("https://registry.example.com").unwrap();
+        let dep = Dependency::parse("acme.com/api@1.0.0").unwrap();
+        let package = unsigned_package(CANON_YML, &[("artifacts/README.md", "# API")]);
+
+        assert!(cache.find(&location, &dep, None).unwrap().is_none());
+        cache
+            .store(&location, &dep, &package, &Verification::Unsigned)
+            .unwrap();
+
+        let entry = cache.find(&location, &dep, None).unwrap().unwrap();
+        assert_eq!(entry.verification(), Verification::Unsigned);
+        assert_eq!(entry.registry, "registry.example.com");
+        assert!(cache
+            .find(&location, &dep, Some("sha256:other"))
+            .unwrap()
+            .is_none());
+        assert_eq!(cache.list().unwrap().len(), 1);
+
+        let project = tempfile::tempdir().unwrap();
+        let target = project.path().join("api");
+        entry.link_into(&target).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.join("artifacts/README.md")).unwrap(),
+            "# API"
+        );
+
+        // A file changed through a hardlink invalidates the entry
+        fs::write(entry.path.join("artifacts/README.md"), "# Changed").unwrap();
+        assert!(entry.check().is_err());
+        assert!(cache.find(&location, &dep, None).unwrap().is_none());
+    }
+}
@@ -0,0 +1,155 @@
+use super::{DownloadedPackage, PublishPackage, Registry, RegistryCapabilities};
+use crate::core::integrity::is_safe_path;
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::Dependency;
+use std::fs;
+use std::path::PathBuf;
+
+/// A registry kept as a directory tree, e.g. on a shared drive
+///
+/// Packages live at `<root>/<publisher>/<id>/<version>/`, the same layout as
+/// `.canon/<registry-host>/`. Capabilities are read from
+/// `<root>/.well-known/canon-registry` when present; otherwise the directory
+/// is treated as plain writable storage.
+pub struct FileRegistry {
+    root: PathBuf,
+}
+
+impl FileRegistry {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn package_dir(&self, dep: &Dependency) -> CanonResult<PathBuf> {
+        Ok(self
+            .root
+            .join(&dep.publisher)
+            .join(&dep.id)
+            .join(dep.pinned_version()?.to_string()))
+    }
+}
+
+impl Registry for FileRegistry {
+    async fn discover(&self) -> CanonResult<RegistryCapabilities> {
+        let path = self.root.join(".well-known").join("canon-registry");
+        if !path.exists() {
+            return Ok(RegistryCapabilities::storage(
+                &self.root.display().to_string(),
+                &format!("file://{}", self.root.display()),
+            ));
+        }
+
+        let content = fs::read(&path).map_err(CanonError::Io)?;
+        serde_yaml::from_slice(&content).map_err(|e| CanonError::Config {
+            message: format!("Failed to parse {}: {}", path.display(), e),
+        })
+    }
+
+    async fn list_versions(&self, publisher: &str, id: &str) -> CanonResult<Vec<String>> {
+        let dir = self.root.join(publisher).join(id);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut versions = Vec::new();
+        for entry in fs::read_dir(&dir).map_err(CanonError::Io)? {
+            let entry = entry.map_err(CanonError::Io)?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with('.') && entry.path().join("canon.yml").is_file() {
+                versions.push(name);
+            }
+        }
+        Ok(versions)
+    }
+
+    async fn fetch(&self, dep: &Dependency, path: &str) -> CanonResult<Option<Vec<u8>>> {
+        if !is_safe_path(path) {
+            return Ok(None);
+        }
+        let file = self.package_dir(dep)?.join(path);
+        if !file.is_file() {
+            return Ok(None);
+        }
+        fs::read(&file).map(Some).map_err(CanonError::Io)
+    }
+
+    /// Copy the package in; published versions are never overwritten
+    async fn publish(&self, package: &PublishPackage<'_>) -> CanonResult<()> {
+        let spec = &package.manifest.specification;
+        let dep = Dependency {
+            publisher: spec.publisher.clone(),
+            id: spec.id.clone(),
+            version: Some(spec.version.clone()),
+            version_operator: None,
+        };
+        let target = self.package_dir(&dep)?;
+        if target.exists() {
+            return Err(CanonError::Command {
+                message: format!("{} is already published to this registry", dep.to_uri()),
+            });
+        }
+
+        let contents = DownloadedPackage::read_dir(package.dir, package.manifest)?;
+
+        // Stage next to the target and rename, so readers never see half a package
+        let parent = target.parent().unwrap_or(&self.root);
+        fs::create_dir_all(parent).map_err(CanonError::Io)?;
+        let staging = parent.join(format!(".{}.{}.tmp", spec.version, uuid::Uuid::new_v4()));
+        let result = (|| {
+            for (path, content) in contents.all_files() {
+                let file = staging.join(path);
+                if let Some(dir) = file.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(file, content)?;
+            }
+            fs::rename(&staging, &target)
+        })();
+
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(CanonError::Io(e));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::registry::memory::unsigned_package;
+    use canon_protocol::CanonManifest;
+
+    const CANON_YML: &str = "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: 1.0.0\n  publisher: acme.com\n";
+
+    #[tokio::test]
+    async fn test_publish_then_download() {
+        let build = tempfile::tempdir().unwrap();
+        let package = unsigned_package(CANON_YML, &[("artifacts/README.md", "# API")]);
+        for (path, content) in package.all_files() {
+            let file = build.path().join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        }
+        let manifest: CanonManifest =
+            serde_yaml::from_slice(package.manifest.as_deref().unwrap()).unwrap();
+
+        let root = tempfile::tempdir().unwrap();
+        let registry = FileRegistry::new(root.path().to_path_buf());
+        let publish = PublishPackage {
+            dir: build.path(),
+            manifest: &manifest,
+            auth_token: None,
+        };
+        registry.publish(&publish).await.unwrap();
+        assert!(registry.publish(&publish).await.is_err());
+
+        let dep = Dependency::parse("acme.com/api@1.0.0").unwrap();
+        assert_eq!(registry.download(&dep).await.unwrap(), package);
+        assert_eq!(
+            registry.list_versions("acme.com", "api").await.unwrap(),
+            vec!["1.0.0".to_string()]
+        );
+        assert_eq!(registry.fetch(&dep, "../../x").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,233 @@
+use super::{DownloadedPackage, PublishPackage, Registry, RegistryCapabilities};
+use crate::core::http::HttpClient;
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::Dependency;
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+/// A registry speaking the Canon Registry HTTP protocol
+pub struct HttpRegistry {
+    url: String,
+    http: HttpClient,
+    capabilities: OnceCell<RegistryCapabilities>,
+}
+
+/// Package listing returned by the `packages` endpoint: either a bare list
+/// or `{ versions: [...] }`, of version strings or `{ version: ... }` objects
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VersionList {
+    Wrapped { versions: Vec<VersionEntry> },
+    Plain(Vec<VersionEntry>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VersionEntry {
+    Version(String),
+    Detailed { version: String },
+}
+
+impl HttpRegistry {
+    pub fn new(url: &str, http: HttpClient) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            http,
+            capabilities: OnceCell::new(),
+        }
+    }
+
+    /// Absolute URL for an advertised endpoint, which may be relative to the registry
+    fn endpoint_url(&self, endpoint: &str) -> String {
+        if endpoint.starts_with("http://") || endpoint.starts_with("https://") {
+            endpoint.to_string()
+        } else {
+            format!("{}{}", self.url, endpoint)
+        }
+    }
+}
+
+impl Registry for HttpRegistry {
+    async fn discover(&self) -> CanonResult<RegistryCapabilities> {
+        let capabilities = self
+            .capabilities
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/canon-registry", self.url);
+                let body = self
+                    .http
+                    .get(&url)
+                    .await?
+                    .ok_or_else(|| CanonError::Network {
+                        message: format!("Registry discovery failed: {} not found", url),
+                    })?;
+                serde_json::from_slice(&body).map_err(|e| CanonError::Network {
+                    message: format!("Failed to parse registry capabilities: {}", e),
+                })
+            })
+            .await?;
+        Ok(capabilities.clone())
+    }
+
+    async fn list_versions(&self, publisher: &str, id: &str) -> CanonResult<Vec<String>> {
+        let capabilities = self.discover().await?;
+        let endpoint = &capabilities.endpoints.packages;
+        let url = if endpoint.contains("{publisher}") || endpoint.contains("{id}") {
+            self.endpoint_url(
+                &endpoint
+                    .replace("{publisher}", publisher)
+                    .replace("{id}", id),
+            )
+        } else {
+            format!(
+                "{}/{}/{}",
+                self.endpoint_url(endpoint).trim_end_matches('/'),
+                publisher,
+                id
+            )
+        };
+
+        let Some(body) = self.http.get(&url).await? else {
+            return Ok(Vec::new());
+        };
+        let list: VersionList = serde_yaml::from_slice(&body).map_err(|e| CanonError::Network {
+            message: format!("Failed to parse version list from {}: {}", url, e),
+        })?;
+        let entries = match list {
+            VersionList::Wrapped { versions } | VersionList::Plain(versions) => versions,
+        };
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| match entry {
+                VersionEntry::Version(version) | VersionEntry::Detailed { version } => version,
+            })
+            .collect())
+    }
+
+    async fn fetch(&self, dep: &Dependency, path: &str) -> CanonResult<Option<Vec<u8>>> {
+        self.http
+            .get(&format!("{}{}", dep.package_url(&self.url)?, path))
+            .await
+    }
+
+    async fn publish(&self, package: &PublishPackage<'_>) -> CanonResult<()> {
+        let capabilities = self.discover().await?;
+        let endpoint =
+            capabilities
+                .endpoints
+                .publish
+                .as_deref()
+                .ok_or_else(|| CanonError::Command {
+                    message: "Registry does not provide a publish endpoint".to_string(),
+                })?;
+        let publish_url = self.endpoint_url(endpoint);
+
+        let spec = &package.manifest.specification;
+        let contents = DownloadedPackage::read_dir(package.dir, package.manifest)?;
+
+        // One part per file, named by its path in the package, as FileRegistry stores them
+        let response = self
+            .http
+            .send(&publish_url, false, |client| {
+                let form = contents
+                    .all_files()
+                    .fold(Form::new(), |form, (path, content)| {
+                        form.part(
+                            path.to_string(),
+                            Part::bytes(content.to_vec()).file_name(path.to_string()),
+                        )
+                    });
+                let request = client
+                    .post(&publish_url)
+                    .header("X-Canon-Publisher", &spec.publisher)
+                    .header("X-Canon-Id", &spec.id)
+                    .header("X-Canon-Version", &spec.version)
+                    .header("X-Canon-Canonical-Hash", &package.manifest.canonical_hash);
+                match package.auth_token {
+                    Some(token) => request.header("Authorization", format!("Bearer {}", token)),
+                    None => request,
+                }
+                .multipart(form)
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(CanonError::Network {
+                message: format!("Publication failed (status: {}): {}", status, body),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::core::registry::memory::unsigned_package;
+    use canon_protocol::CanonManifest;
+    use std::fs;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const CANON_YML: &str = "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: 1.0.0\n  publisher: acme.com\n";
+
+    #[tokio::test]
+    async fn test_publish_uploads_every_file() {
+        let server = MockServer::start().await;
+        let capabilities = serde_json::json!({
+            "url": server.uri(),
+            "api_version": "1.0",
+            "read_only": false,
+            "endpoints": {
+                "discovery": "/.well-known/canon-registry",
+                "packages": "/packages",
+                "download": "/packages",
+                "publish": "/publish"
+            }
+        });
+        Mock::given(method("GET"))
+            .and(path("/.well-known/canon-registry"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(capabilities))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/publish"))
+            .and(header("X-Canon-Id", "api"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let build = tempfile::tempdir().unwrap();
+        let package = unsigned_package(CANON_YML, &[("artifacts/README.md", "# API")]);
+        for (path, content) in package.all_files() {
+            let file = build.path().join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        }
+        let manifest: CanonManifest =
+            serde_yaml::from_slice(package.manifest.as_deref().unwrap()).unwrap();
+
+        let http = HttpClient::from_config(&Config::defaults()).unwrap();
+        HttpRegistry::new(&server.uri(), http)
+            .publish(&PublishPackage {
+                dir: build.path(),
+                manifest: &manifest,
+                auth_token: None,
+            })
+            .await
+            .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let body = String::from_utf8_lossy(&requests.last().unwrap().body).into_owned();
+        for (path, _) in package.all_files() {
+            assert!(body.contains(&format!("filename=\"{}\"", path)), "{}", path);
+        }
+        assert!(body.contains("# API"));
+    }
+}
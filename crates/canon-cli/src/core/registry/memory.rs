@@ -0,0 +1,100 @@
+use super::{DownloadedPackage, PublishPackage, Registry, RegistryCapabilities};
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::{
+    CanonManifest, CanonSignature, CanonSpecification, Dependency, ManifestFile,
+    ManifestSpecification,
+};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// A registry held in memory, for tests
+#[derive(Default)]
+pub struct MemoryRegistry {
+    packages: Mutex<BTreeMap<String, DownloadedPackage>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a package under `publisher/id@version`
+    pub fn insert(&self, uri: &str, package: DownloadedPackage) {
+        self.packages
+            .lock()
+            .unwrap()
+            .insert(uri.to_string(), package);
+    }
+}
+
+impl Registry for MemoryRegistry {
+    async fn discover(&self) -> CanonResult<RegistryCapabilities> {
+        Ok(RegistryCapabilities::storage("memory", "memory://"))
+    }
+
+    async fn list_versions(&self, publisher: &str, id: &str) -> CanonResult<Vec<String>> {
+        let prefix = format!("{}/{}@", publisher, id);
+        Ok(self
+            .packages
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|uri| uri.strip_prefix(&prefix))
+            .map(str::to_string)
+            .collect())
+    }
+
+    async fn fetch(&self, dep: &Dependency, path: &str) -> CanonResult<Option<Vec<u8>>> {
+        let packages = self.packages.lock().unwrap();
+        let Some(package) = packages.get(&dep.to_uri()) else {
+            return Ok(None);
+        };
+        Ok(match path {
+            CanonManifest::FILE_NAME => package.manifest.clone(),
+            CanonSignature::FILE_NAME => package.signature.clone(),
+            _ => package.files.get(path).cloned(),
+        })
+    }
+
+    async fn publish(&self, package: &PublishPackage<'_>) -> CanonResult<()> {
+        let spec = &package.manifest.specification;
+        let uri = format!("{}/{}@{}", spec.publisher, spec.id, spec.version);
+        if self.packages.lock().unwrap().contains_key(&uri) {
+            return Err(CanonError::Command {
+                message: format!("{} is already published to this registry", uri),
+            });
+        }
+
+        let contents = DownloadedPackage::read_dir(package.dir, package.manifest)?;
+        self.insert(&uri, contents);
+        Ok(())
+    }
+}
+
+/// An unsigned package whose manifest covers canon.yml and `files`
+pub fn unsigned_package(canon_yml: &str, files: &[(&str, &str)]) -> DownloadedPackage {
+    let spec: CanonSpecification = serde_yaml::from_str(canon_yml).unwrap();
+    let mut contents = BTreeMap::from([("canon.yml".to_string(), canon_yml.as_bytes().to_vec())]);
+    for (path, content) in files {
+        contents.insert(path.to_string(), content.as_bytes().to_vec());
+    }
+
+    let manifest = CanonManifest::new(
+        ManifestSpecification {
+            id: spec.metadata.id,
+            version: spec.metadata.version,
+            publisher: spec.metadata.publisher,
+            r#type: spec.r#type,
+        },
+        contents
+            .iter()
+            .map(|(path, content)| ManifestFile::from_contents(path, content))
+            .collect(),
+    );
+
+    DownloadedPackage {
+        files: contents,
+        manifest: Some(serde_yaml::to_string(&manifest).unwrap().into_bytes()),
+        signature: None,
+    }
+}
@@ -0,0 +1,398 @@
+pub mod file;
+pub mod http;
+#[cfg(test)]
+pub mod memory;
+
+use crate::config::Config;
+use crate::core::http::HttpClient;
+use crate::core::integrity::is_safe_path;
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::{CanonManifest, CanonSignature, Dependency};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+pub use file::FileRegistry;
+pub use http::HttpRegistry;
+
+/// Registry capability discovery response (`/.well-known/canon-registry`)
+#[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
+pub struct RegistryCapabilities {
+    #[serde(default)]
+    pub name: String,
+    pub url: String,
+    pub api_version: String,
+    pub read_only: bool,
+    #[serde(default)]
+    pub authentication: Option<AuthenticationConfig>,
+    #[serde(default)]
+    pub verification: Option<VerificationConfig>,
+    pub endpoints: EndpointsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
+pub struct AuthenticationConfig {
+    pub required: bool,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(default)]
+    pub endpoints: Option<AuthEndpoints>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
+pub struct AuthEndpoints {
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub oauth: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerificationConfig {
+    pub methods: Vec<String>,
+    #[serde(default)]
+    pub dns_txt: Option<DnsVerificationConfig>,
+    #[serde(default)]
+    pub https_file: Option<HttpsVerificationConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
+pub struct DnsVerificationConfig {
+    pub prefix: String,
+    pub format: String,
+    #[serde(default)]
+    pub ttl: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
+pub struct HttpsVerificationConfig {
+    pub path: String,
+    pub format: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
+pub struct EndpointsConfig {
+    pub discovery: String,
+    pub packages: String,
+    pub download: String,
+    #[serde(default)]
+    pub publish: Option<String>,
+}
+
+impl RegistryCapabilities {
+    /// Capabilities of a registry that is just storage: writable, no
+    /// authentication or domain verification
+    pub fn storage(name: &str, url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            api_version: "1.0".to_string(),
+            read_only: false,
+            authentication: None,
+            verification: None,
+            endpoints: EndpointsConfig {
+                discovery: "/.well-known/canon-registry".to_string(),
+                packages: String::new(),
+                download: String::new(),
+                publish: Some(String::new()),
+            },
+        }
+    }
+}
+
+/// The files of one package as fetched from a registry
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadedPackage {
+    /// Package contents by relative path, including canon.yml
+    pub files: BTreeMap<String, Vec<u8>>,
+    pub manifest: Option<Vec<u8>>,
+    pub signature: Option<Vec<u8>>,
+}
+
+impl DownloadedPackage {
+    /// Read a built package directory: canon.yml, every file its manifest
+    /// lists, the manifest itself and the signature if there is one
+    pub fn read_dir(dir: &Path, manifest: &CanonManifest) -> CanonResult<Self> {
+        let mut files = BTreeMap::new();
+        files.insert(
+            "canon.yml".to_string(),
+            fs::read(dir.join("canon.yml")).map_err(CanonError::Io)?,
+        );
+        for file in &manifest.files {
+            files.insert(
+                file.path.clone(),
+                fs::read(dir.join(&file.path)).map_err(CanonError::Io)?,
+            );
+        }
+
+        let signature_path = dir.join(CanonSignature::FILE_NAME);
+        Ok(Self {
+            files,
+            manifest: Some(fs::read(dir.join(CanonManifest::FILE_NAME)).map_err(CanonError::Io)?),
+            signature: signature_path
+                .exists()
+                .then(|| fs::read(&signature_path))
+                .transpose()
+                .map_err(CanonError::Io)?,
+        })
+    }
+
+    /// Every file to store, with the manifest and signature under their usual names
+    pub fn all_files(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_slice()))
+            .chain(
+                self.manifest
+                    .as_deref()
+                    .map(|content| (CanonManifest::FILE_NAME, content)),
+            )
+            .chain(
+                self.signature
+                    .as_deref()
+                    .map(|content| (CanonSignature::FILE_NAME, content)),
+            )
+    }
+}
+
+/// A built package to publish
+pub struct PublishPackage<'a> {
+    /// Directory holding the built package
+    pub dir: &'a Path,
+    pub manifest: &'a CanonManifest,
+    pub auth_token: Option<&'a str>,
+}
+
+/// Storage that serves and accepts Canon packages
+///
+/// Packages are addressed as `<publisher>/<id>/<version>/<file>`, the same
+/// layout used under `.canon/<registry-host>/`.
+pub trait Registry {
+    /// What the registry supports (publishing, authentication, verification)
+    async fn discover(&self) -> CanonResult<RegistryCapabilities>;
+
+    /// Every published version of `publisher/id`, in no particular order
+    async fn list_versions(&self, publisher: &str, id: &str) -> CanonResult<Vec<String>>;
+
+    /// One file of a package, or `None` if the registry does not have it
+    async fn fetch(&self, dep: &Dependency, path: &str) -> CanonResult<Option<Vec<u8>>>;
+
+    /// Store a built package
+    async fn publish(&self, package: &PublishPackage<'_>) -> CanonResult<()>;
+
+    /// Fetch canon.yml, the manifest and signature, and every file the manifest lists
+    ///
+    /// Nothing is verified here; paths the manifest lists that could escape
+    /// the package are not fetched, which leaves verification to reject it.
+    async fn download(&self, dep: &Dependency) -> CanonResult<DownloadedPackage> {
+        let (canon_yml, manifest, signature) = tokio::try_join!(
+            self.fetch(dep, "canon.yml"),
+            self.fetch(dep, CanonManifest::FILE_NAME),
+            self.fetch(dep, CanonSignature::FILE_NAME),
+        )?;
+        let canon_yml = canon_yml.ok_or_else(|| CanonError::RegistryError {
+            url: dep.to_uri(),
+            status: "not found".to_string(),
+        })?;
+
+        let mut package = DownloadedPackage {
+            files: BTreeMap::from([("canon.yml".to_string(), canon_yml)]),
+            manifest,
+            signature,
+        };
+
+        let listed: Vec<String> = package
+            .manifest
+            .as_deref()
+            .and_then(|content| serde_yaml::from_slice::<CanonManifest>(content).ok())
+            .map(|manifest| manifest.files.into_iter().map(|f| f.path).collect())
+            .unwrap_or_default();
+        for path in listed {
+            if package.files.contains_key(&path) || !is_safe_path(&path) {
+                continue;
+            }
+            if let Some(content) = self.fetch(dep, &path).await? {
+                package.files.insert(path, content);
+            }
+        }
+
+        Ok(package)
+    }
+}
+
+/// A registry backend chosen by URL scheme
+pub enum AnyRegistry {
+    Http(Box<HttpRegistry>),
+    File(FileRegistry),
+}
+
+impl Registry for AnyRegistry {
+    async fn discover(&self) -> CanonResult<RegistryCapabilities> {
+        match self {
+            Self::Http(registry) => registry.discover().await,
+            Self::File(registry) => registry.discover().await,
+        }
+    }
+
+    async fn list_versions(&self, publisher: &str, id: &str) -> CanonResult<Vec<String>> {
+        match self {
+            Self::Http(registry) => registry.list_versions(publisher, id).await,
+            Self::File(registry) => registry.list_versions(publisher, id).await,
+        }
+    }
+
+    async fn fetch(&self, dep: &Dependency, path: &str) -> CanonResult<Option<Vec<u8>>> {
+        match self {
+            Self::Http(registry) => registry.fetch(dep, path).await,
+            Self::File(registry) => registry.fetch(dep, path).await,
+        }
+    }
+
+    async fn publish(&self, package: &PublishPackage<'_>) -> CanonResult<()> {
+        match self {
+            Self::Http(registry) => registry.publish(package).await,
+            Self::File(registry) => registry.publish(package).await,
+        }
+    }
+}
+
+/// The registry a command talks to and where its packages are cached
+///
+/// Packages from `https://registry.example.com` are stored under
+/// `.canon/registry.example.com/`; a non-default port is appended as
+/// `_<port>` so the directory name stays valid on every platform. A
+/// `file://` registry is cached under `file_<path>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryLocation {
+    url: String,
+    dir_name: String,
+}
+
+impl RegistryLocation {
+    pub fn parse(url: &str) -> CanonResult<Self> {
+        let parsed = Url::parse(url).map_err(|e| CanonError::Config {
+            message: format!("Invalid registry URL '{}': {}", url, e),
+        })?;
+
+        let dir_name = match parsed.scheme() {
+            "http" | "https" => {
+                let host = parsed.host_str().ok_or_else(|| CanonError::Config {
+                    message: format!("Registry URL '{}' has no host", url),
+                })?;
+                match parsed.port() {
+                    Some(port) => format!("{}_{}", host, port),
+                    None => host.to_string(),
+                }
+            }
+            "file" => {
+                let path = parsed
+                    .path()
+                    .split(['/', '\\', ':'])
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("_");
+                format!("file_{}", path)
+            }
+            scheme => {
+                return Err(CanonError::Config {
+                    message: format!(
+                        "Unsupported registry scheme '{}' in '{}' (expected http, https or file)",
+                        scheme, url
+                    ),
+                })
+            }
+        };
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            dir_name,
+        })
+    }
+
+    /// `--registry` if given, otherwise `registry.default` from config
+    pub fn from_config(config: &Config) -> CanonResult<Self> {
+        Self::parse(&config.default_registry())
+    }
+
+    /// Registry base URL, without a trailing slash
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Directory name for this registry under `.canon/` and the package cache
+    pub fn dir_name(&self) -> &str {
+        &self.dir_name
+    }
+
+    /// Base URL of a package on this registry, ending in `/`
+    pub fn package_url(&self, dep: &Dependency) -> CanonResult<String> {
+        Ok(dep.package_url(&self.url)?)
+    }
+
+    /// Where a package from this registry is installed, relative to the project
+    pub fn local_path(&self, dep: &Dependency) -> CanonResult<PathBuf> {
+        Ok(dep.local_path_with_registry(&self.dir_name)?)
+    }
+
+    /// Open the backend for this registry's URL scheme
+    pub fn connect(&self, http: &HttpClient) -> CanonResult<AnyRegistry> {
+        if self.url.starts_with("file:") {
+            let path = Url::parse(&self.url)
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or_else(|| CanonError::Config {
+                    message: format!("Invalid file registry path '{}'", self.url),
+                })?;
+            Ok(AnyRegistry::File(FileRegistry::new(path)))
+        } else {
+            Ok(AnyRegistry::Http(Box::new(HttpRegistry::new(
+                &self.url,
+                http.clone(),
+            ))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_path_uses_registry_host() {
+        let dep = Dependency::parse("acme.com/api@1.0.0").unwrap();
+
+        let registry = RegistryLocation::parse("https://canon.canon-protocol.org/").unwrap();
+        assert_eq!(registry.url(), "https://canon.canon-protocol.org");
+        assert_eq!(
+            registry.local_path(&dep).unwrap(),
+            dep.local_path().unwrap()
+        );
+
+        let registry = RegistryLocation::parse("http://registry.internal:8080").unwrap();
+        assert_eq!(
+            registry.local_path(&dep).unwrap(),
+            PathBuf::from(".canon/registry.internal_8080/acme.com/api/1.0.0")
+        );
+        assert_eq!(
+            registry.package_url(&dep).unwrap(),
+            "http://registry.internal:8080/acme.com/api/1.0.0/"
+        );
+
+        let registry = RegistryLocation::parse("file:///mnt/specs").unwrap();
+        assert_eq!(
+            registry.local_path(&dep).unwrap(),
+            PathBuf::from(".canon/file_mnt_specs/acme.com/api/1.0.0")
+        );
+
+        assert!(RegistryLocation::parse("not a url").is_err());
+        assert!(RegistryLocation::parse("ftp://example.com").is_err());
+    }
+}
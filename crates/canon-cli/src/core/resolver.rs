@@ -0,0 +1,689 @@
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::semver::{Op, Version, VersionReq};
+use canon_protocol::{CanonLock, CanonSpecification, Dependency};
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Where the resolver gets specifications from
+///
+/// `load` may be called for several packages at once.
+pub trait SpecSource {
+    /// Make `dep` (pinned to an exact version) available and return its specification
+    async fn load(&self, dep: &Dependency) -> CanonResult<CanonSpecification>;
+
+    /// Every version of `dep`'s package the source offers
+    ///
+    /// Only asked for packages being upgraded and for ranges the lock does
+    /// not settle; other packages are chosen from the lock and the exact
+    /// versions their requirements name.
+    async fn versions(&self, _dep: &Dependency) -> CanonResult<Vec<Version>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Which packages may move off the version recorded in the lock
+#[derive(Debug, Clone, Default)]
+pub enum Upgrade {
+    /// Keep every locked version
+    #[default]
+    None,
+    /// Move every package to the newest version its requirements allow
+    All,
+    /// Move only these `publisher/id` keys
+    Only(BTreeSet<String>),
+}
+
+impl Upgrade {
+    /// Whether the package `publisher/id` may move
+    pub fn includes(&self, key: &str) -> bool {
+        match self {
+            Self::None => false,
+            Self::All => true,
+            Self::Only(keys) => keys.contains(key),
+        }
+    }
+}
+
+/// A version constraint on a package and who declared it
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    pub dependency: Dependency,
+    pub req: VersionReq,
+    /// `publisher/id` of the requiring package, `None` for canon.yml itself
+    pub required_by: Option<String>,
+}
+
+/// A package in the resolved dependency closure
+#[derive(Debug, Clone)]
+pub struct ResolvedPackage {
+    /// The dependency pinned to its selected version
+    pub dependency: Dependency,
+    /// Declared directly in canon.yml
+    pub direct: bool,
+    /// Packages that include or reference this one
+    pub required_by: Vec<String>,
+    /// Packages this one includes or references
+    pub requires: Vec<String>,
+}
+
+/// Result of resolving the full dependency closure
+#[derive(Debug, Default)]
+pub struct Resolution {
+    /// Resolved packages keyed by `publisher/id`
+    pub packages: BTreeMap<String, ResolvedPackage>,
+    /// Dependency cycles, each listed from its first package back to itself
+    pub cycles: Vec<Vec<String>>,
+    /// Packages that could not be loaded, with the reason
+    pub failures: Vec<(String, CanonError)>,
+}
+
+/// Resolves direct dependencies plus everything they reach through
+/// `includes` and `ref` fields, unifying the version ranges each package
+/// is requested with
+pub struct Resolver<'a> {
+    lock: Option<&'a CanonLock>,
+    requirements: BTreeMap<String, Vec<Requirement>>,
+    selected: BTreeMap<String, Version>,
+    expanded: HashMap<(String, Version), Vec<Requirement>>,
+    /// Load and parse failures of each loaded version
+    failures: HashMap<(String, Version), Vec<(String, CanonError)>>,
+    parallel: usize,
+    upgrade: Upgrade,
+    available: HashMap<String, Vec<Version>>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Create a resolver that prefers versions recorded in `lock`
+    pub fn new(lock: Option<&'a CanonLock>) -> Self {
+        Self {
+            lock,
+            requirements: BTreeMap::new(),
+            selected: BTreeMap::new(),
+            expanded: HashMap::new(),
+            failures: HashMap::new(),
+            parallel: 1,
+            upgrade: Upgrade::None,
+            available: HashMap::new(),
+        }
+    }
+
+    /// Load up to `parallel` packages at once (default 1)
+    pub fn parallel(mut self, parallel: usize) -> Self {
+        self.parallel = parallel.max(1);
+        self
+    }
+
+    /// Let `upgrade` packages ignore the lock and take the newest version
+    /// the source offers that satisfies their requirements
+    pub fn upgrade(mut self, upgrade: Upgrade) -> Self {
+        self.upgrade = upgrade;
+        self
+    }
+
+    /// Resolve the closure of `roots`, loading each selected package from `source`
+    ///
+    /// Works in rounds: the requirements are gathered from the roots and the
+    /// children of each package's currently selected version, versions are
+    /// selected, and all newly selected packages are loaded together. A
+    /// package that moves to another version takes its old children's
+    /// requirements with it. Rounds continue until nothing changes.
+    pub async fn resolve<S: SpecSource>(
+        mut self,
+        roots: &[Dependency],
+        source: &S,
+    ) -> CanonResult<Resolution> {
+        let mut root_requirements = Vec::new();
+        for dep in roots {
+            root_requirements.push(Requirement::new(dep.clone(), None)?);
+        }
+
+        let mut seen = HashSet::new();
+        loop {
+            self.requirements = self.current_requirements(&root_requirements);
+
+            // Packages being upgraded, and ranges the lock does not settle,
+            // may take any version the source offers
+            let listing: Vec<String> = self
+                .requirements
+                .keys()
+                .filter(|key| !self.available.contains_key(*key) && self.needs_listing(key))
+                .cloned()
+                .collect();
+            let listed: Vec<_> = stream::iter(&listing)
+                .map(|key| source.versions(&self.requirements[key][0].dependency))
+                .buffered(self.parallel)
+                .collect()
+                .await;
+            for (key, versions) in listing.into_iter().zip(listed) {
+                self.available.insert(key, versions?);
+            }
+
+            // A conflict may only last until a package it comes from moves,
+            // so keep the previous choice and report it once nothing changes
+            let mut selected = BTreeMap::new();
+            let mut conflict = None;
+            for key in self.requirements.keys() {
+                match self.select(key) {
+                    Ok(version) => {
+                        selected.insert(key.clone(), version);
+                    }
+                    Err(e) => {
+                        if let Some(previous) = self.selected.get(key) {
+                            selected.insert(key.clone(), previous.clone());
+                        }
+                        conflict.get_or_insert(e);
+                    }
+                }
+            }
+
+            let pending: Vec<(String, Version, Dependency)> = selected
+                .iter()
+                .filter(|(key, version)| {
+                    !self
+                        .expanded
+                        .contains_key(&((*key).clone(), (*version).clone()))
+                })
+                .map(|(key, version)| {
+                    let pinned = pin(&self.requirements[key][0].dependency, version);
+                    (key.clone(), version.clone(), pinned)
+                })
+                .collect();
+
+            if pending.is_empty() {
+                if selected == self.selected {
+                    match conflict {
+                        Some(e) => return Err(e),
+                        None => break,
+                    }
+                }
+                if !seen.insert(selected.clone()) {
+                    return Err(conflict.unwrap_or_else(|| CanonError::Command {
+                        message: "Dependency versions do not settle on a single selection"
+                            .to_string(),
+                    }));
+                }
+            }
+            self.selected = selected;
+
+            // `buffered` keeps results in request order, so resolution is deterministic
+            let loaded: Vec<_> = stream::iter(&pending)
+                .map(|(_, _, pinned)| source.load(pinned))
+                .buffered(self.parallel)
+                .collect()
+                .await;
+
+            for ((key, version, pinned), result) in pending.into_iter().zip(loaded) {
+                let mut failures = Vec::new();
+                let children = match result {
+                    Ok(spec) => child_requirements(&key, &spec, &mut failures),
+                    Err(e) => {
+                        failures.push((pinned.to_uri(), e));
+                        Vec::new()
+                    }
+                };
+                self.failures
+                    .insert((key.clone(), version.clone()), failures);
+                self.expanded.insert((key, version), children);
+            }
+        }
+
+        self.finish(roots)
+    }
+
+    /// Requirements from the roots and from the loaded children of every
+    /// package reachable through the current selection
+    fn current_requirements(&self, roots: &[Requirement]) -> BTreeMap<String, Vec<Requirement>> {
+        let mut requirements: BTreeMap<String, Vec<Requirement>> = BTreeMap::new();
+        let mut queue: Vec<Requirement> = roots.iter().rev().cloned().collect();
+        let mut visited = HashSet::new();
+
+        while let Some(requirement) = queue.pop() {
+            let key = package_key(&requirement.dependency);
+            requirements
+                .entry(key.clone())
+                .or_default()
+                .push(requirement);
+            if !visited.insert(key.clone()) {
+                continue;
+            }
+            if let Some(version) = self.selected.get(&key) {
+                if let Some(children) = self.expanded.get(&(key, version.clone())) {
+                    queue.extend(children.iter().rev().cloned());
+                }
+            }
+        }
+        requirements
+    }
+
+    /// The version canon.lock records for `key`, unless it is being upgraded
+    fn locked(&self, key: &str) -> Option<Version> {
+        self.lock
+            .filter(|_| !self.upgrade.includes(key))
+            .and_then(|l| l.find(&self.requirements[key][0].dependency))
+            .and_then(|p| Version::parse(&p.version).ok())
+    }
+
+    /// Whether `key` has to be chosen from the versions the source offers:
+    /// it is being upgraded, or a requirement on it is a range (or no
+    /// version at all) that its locked version does not satisfy
+    fn needs_listing(&self, key: &str) -> bool {
+        if self.upgrade.includes(key) {
+            return true;
+        }
+        let requirements = &self.requirements[key];
+        if requirements.iter().all(|r| is_exact(&r.req)) {
+            return false;
+        }
+        self.locked(key)
+            .is_none_or(|version| !requirements.iter().all(|r| r.req.matches(&version)))
+    }
+
+    /// Pick the highest candidate version that satisfies every requirement on `key`
+    fn select(&self, key: &str) -> CanonResult<Version> {
+        let requirements = &self.requirements[key];
+
+        let candidates = self
+            .locked(key)
+            .into_iter()
+            .chain(requirements.iter().filter_map(|r| {
+                // An exact pin keeps any build metadata the range drops
+                r.dependency
+                    .pinned_version()
+                    .ok()
+                    .or_else(|| range_floor(&r.req))
+            }))
+            .chain(self.available.get(key).into_iter().flatten().cloned());
+
+        if let Some(version) = candidates
+            .filter(|v| requirements.iter().all(|r| r.req.matches(v)))
+            .max()
+        {
+            return Ok(version);
+        }
+
+        if let [only] = requirements.as_slice() {
+            return Err(CanonError::Command {
+                message: format!(
+                    "No available version of {} matches '{}'",
+                    key,
+                    only.dependency.to_uri()
+                ),
+            });
+        }
+
+        let wanted = requirements
+            .iter()
+            .map(|r| {
+                format!(
+                    "{} (required by {})",
+                    r.dependency.to_uri(),
+                    r.required_by.as_deref().unwrap_or("canon.yml")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(CanonError::Command {
+            message: format!("Conflicting version requirements for {}: {}", key, wanted),
+        })
+    }
+
+    /// Walk the final graph from the roots using only the selected versions
+    fn finish(mut self, roots: &[Dependency]) -> CanonResult<Resolution> {
+        let mut packages: BTreeMap<String, ResolvedPackage> = BTreeMap::new();
+        let mut edges: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut stack: Vec<String> = roots.iter().map(package_key).collect();
+
+        for key in &stack {
+            let version = &self.selected[key];
+            let dependency = pin(&self.requirements[key][0].dependency, version);
+            packages.entry(key.clone()).or_insert(ResolvedPackage {
+                dependency,
+                direct: true,
+                required_by: Vec::new(),
+                requires: Vec::new(),
+            });
+        }
+
+        let mut visited = HashSet::new();
+        let mut failures = Vec::new();
+        while let Some(key) = stack.pop() {
+            if !visited.insert(key.clone()) {
+                continue;
+            }
+
+            let version = self.selected[&key].clone();
+            if let Some(failed) = self.failures.remove(&(key.clone(), version.clone())) {
+                failures.extend(failed);
+            }
+            let children = &self.expanded[&(key.clone(), version)];
+
+            for child in children {
+                let child_key = package_key(&child.dependency);
+                let child_version = &self.selected[&child_key];
+                if !child.req.matches(child_version) {
+                    return Err(CanonError::Command {
+                        message: format!(
+                            "{} requires {} but {} was selected",
+                            key,
+                            child.dependency.to_uri(),
+                            child_version
+                        ),
+                    });
+                }
+
+                let package =
+                    packages
+                        .entry(child_key.clone())
+                        .or_insert_with(|| ResolvedPackage {
+                            dependency: pin(&child.dependency, child_version),
+                            direct: false,
+                            required_by: Vec::new(),
+                            requires: Vec::new(),
+                        });
+                if !package.required_by.contains(&key) {
+                    package.required_by.push(key.clone());
+                }
+
+                let out = edges.entry(key.clone()).or_default();
+                if !out.contains(&child_key) {
+                    out.push(child_key.clone());
+                }
+                stack.push(child_key);
+            }
+        }
+
+        for (key, out) in &edges {
+            if let Some(package) = packages.get_mut(key) {
+                package.requires = out.clone();
+            }
+        }
+
+        let cycles = find_cycles(&edges);
+
+        Ok(Resolution {
+            packages,
+            cycles,
+            failures,
+        })
+    }
+}
+
+/// Parse the includes and refs of a loaded specification
+fn child_requirements(
+    key: &str,
+    spec: &CanonSpecification,
+    failures: &mut Vec<(String, CanonError)>,
+) -> Vec<Requirement> {
+    let mut children = Vec::new();
+    for uri in spec.references() {
+        match Dependency::parse(&uri)
+            .map_err(CanonError::Protocol)
+            .and_then(|dep| Requirement::new(dep, Some(key.to_string())))
+        {
+            Ok(child) => children.push(child),
+            Err(e) => failures.push((uri, e)),
+        }
+    }
+    children
+}
+
+impl Requirement {
+    fn new(dependency: Dependency, required_by: Option<String>) -> CanonResult<Self> {
+        let req = dependency.version_req().map_err(CanonError::Protocol)?;
+        Ok(Self {
+            dependency,
+            req,
+            required_by,
+        })
+    }
+}
+
+/// `publisher/id` identifying a package regardless of version
+pub fn package_key(dep: &Dependency) -> String {
+    format!("{}/{}", dep.publisher, dep.id)
+}
+
+/// The lowest version a single `=`, `^` or `~` range names, if it has one
+pub fn range_floor(req: &VersionReq) -> Option<Version> {
+    match req.comparators.as_slice() {
+        [c] if matches!(c.op, Op::Exact | Op::Caret | Op::Tilde) => {
+            c.minor.zip(c.patch).map(|(minor, patch)| Version {
+                major: c.major,
+                minor,
+                patch,
+                pre: c.pre.clone(),
+                build: Default::default(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Whether `req` names exactly one version, e.g. `=1.2.3`
+fn is_exact(req: &VersionReq) -> bool {
+    matches!(req.comparators.as_slice(), [c] if c.op == Op::Exact && c.patch.is_some())
+}
+
+/// Copy of `dep` pinned to exactly `version`
+fn pin(dep: &Dependency, version: &Version) -> Dependency {
+    Dependency {
+        publisher: dep.publisher.clone(),
+        id: dep.id.clone(),
+        version: Some(version.to_string()),
+        version_operator: None,
+    }
+}
+
+/// Find every elementary cycle reachable by depth-first search
+fn find_cycles(edges: &BTreeMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    fn visit(
+        node: &str,
+        edges: &BTreeMap<String, Vec<String>>,
+        path: &mut Vec<String>,
+        done: &mut HashSet<String>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(start) = path.iter().position(|p| p == node) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(node.to_string());
+            cycles.push(cycle);
+            return;
+        }
+        if done.contains(node) {
+            return;
+        }
+
+        path.push(node.to_string());
+        for next in edges.get(node).into_iter().flatten() {
+            visit(next, edges, path, done, cycles);
+        }
+        path.pop();
+        done.insert(node.to_string());
+    }
+
+    let mut cycles = Vec::new();
+    let mut done = HashSet::new();
+    for node in edges.keys() {
+        visit(node, edges, &mut Vec::new(), &mut done, &mut cycles);
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// In-memory source keyed by `publisher/id@version`
+    struct MemorySource(HashMap<String, CanonSpecification>);
+
+    impl SpecSource for MemorySource {
+        async fn load(&self, dep: &Dependency) -> CanonResult<CanonSpecification> {
+            self.0
+                .get(&dep.to_uri())
+                .cloned()
+                .ok_or_else(|| CanonError::Command {
+                    message: format!("{} not found", dep.to_uri()),
+                })
+        }
+
+        async fn versions(&self, dep: &Dependency) -> CanonResult<Vec<Version>> {
+            let prefix = format!("{}@", package_key(dep));
+            Ok(self
+                .0
+                .keys()
+                .filter_map(|uri| uri.strip_prefix(&prefix))
+                .filter_map(|v| Version::parse(v).ok())
+                .collect())
+        }
+    }
+
+    fn spec(uri: &str, includes: &[&str]) -> (String, CanonSpecification) {
+        let dep = Dependency::parse(uri).unwrap();
+        let yaml = format!(
+            "canon: '1.0'\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: {}\n  version: {}\n  publisher: {}\n",
+            dep.id,
+            dep.version.as_deref().unwrap(),
+            dep.publisher
+        );
+        let mut spec: CanonSpecification = serde_yaml::from_str(&yaml).unwrap();
+        spec.includes = Some(includes.iter().map(|s| s.to_string()).collect());
+        (uri.to_string(), spec)
+    }
+
+    #[tokio::test]
+    async fn test_resolves_transitive_closure_and_unifies_ranges() {
+        let source = MemorySource(HashMap::from([
+            spec("a.org/app@1.0.0", &["b.org/lib@^1.0.0", "c.org/util@1.0.0"]),
+            spec("b.org/lib@1.0.0", &[]),
+            spec("b.org/lib@1.2.0", &["c.org/util@1.0.0"]),
+            spec("c.org/util@1.0.0", &["b.org/lib@^1.2.0"]),
+        ]));
+        let roots = vec![Dependency::parse("a.org/app@1.0.0").unwrap()];
+
+        let resolution = Resolver::new(None)
+            .parallel(4)
+            .resolve(&roots, &source)
+            .await
+            .unwrap();
+
+        assert!(resolution.failures.is_empty());
+        let lib = &resolution.packages["b.org/lib"];
+        assert_eq!(lib.dependency.version.as_deref(), Some("1.2.0"));
+        assert!(!lib.direct);
+        assert_eq!(resolution.packages.len(), 3);
+        assert_eq!(
+            resolution.cycles,
+            vec![vec!["b.org/lib", "c.org/util", "b.org/lib"]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reports_conflicting_requirements() {
+        let source = MemorySource(HashMap::from([
+            spec("a.org/app@1.0.0", &["b.org/lib@1.0.0"]),
+            spec("b.org/lib@2.0.0", &[]),
+        ]));
+        let roots = vec![
+            Dependency::parse("a.org/app@1.0.0").unwrap(),
+            Dependency::parse("b.org/lib@2.0.0").unwrap(),
+        ];
+
+        let err = Resolver::new(None)
+            .resolve(&roots, &source)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Conflicting version requirements"));
+    }
+
+    #[tokio::test]
+    async fn test_moving_a_package_drops_its_old_children() {
+        let source = MemorySource(HashMap::from([
+            spec("a.org/app@1.0.0", &["b.org/lib@^1.2.0", "c.org/x@^2.0.0"]),
+            spec("b.org/lib@1.0.0", &["c.org/x@1.0.0"]),
+            spec("b.org/lib@1.2.0", &["c.org/x@2.0.0"]),
+            spec("c.org/x@1.0.0", &[]),
+            spec("c.org/x@2.0.0", &[]),
+        ]));
+        let roots = vec![
+            Dependency::parse("b.org/lib@^1.0.0").unwrap(),
+            Dependency::parse("a.org/app@1.0.0").unwrap(),
+        ];
+        let lock = CanonLock::new(&[], vec![spec_lock("b.org", "lib", "1.0.0")]);
+
+        let resolved = Resolver::new(Some(&lock))
+            .resolve(&roots, &source)
+            .await
+            .unwrap();
+        let version = |key: &str| resolved.packages[key].dependency.version.clone().unwrap();
+        assert_eq!(version("b.org/lib"), "1.2.0");
+        assert_eq!(version("c.org/x"), "2.0.0");
+        assert_eq!(resolved.packages["c.org/x"].required_by.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_takes_newest_allowed_version() {
+        let source = MemorySource(HashMap::from([
+            spec("a.org/app@1.0.0", &["b.org/lib@^1.0.0"]),
+            spec("a.org/app@1.1.0", &["b.org/lib@^1.0.0"]),
+            spec("b.org/lib@1.0.0", &[]),
+            spec("b.org/lib@1.4.0", &[]),
+            spec("b.org/lib@2.0.0", &[]),
+        ]));
+        let roots = vec![Dependency::parse("a.org/app@^1.0.0").unwrap()];
+        let lock = CanonLock::new(
+            &[],
+            vec![
+                spec_lock("a.org", "app", "1.0.0"),
+                spec_lock("b.org", "lib", "1.0.0"),
+            ],
+        );
+
+        let upgraded = Resolver::new(Some(&lock))
+            .upgrade(Upgrade::Only(BTreeSet::from(["b.org/lib".to_string()])))
+            .resolve(&roots, &source)
+            .await
+            .unwrap();
+        let version = |key: &str| upgraded.packages[key].dependency.version.clone().unwrap();
+        assert_eq!(version("a.org/app"), "1.0.0");
+        assert_eq!(version("b.org/lib"), "1.4.0");
+    }
+
+    #[tokio::test]
+    async fn test_unversioned_and_ranged_use_listing_unless_locked() {
+        let source = MemorySource(HashMap::from([
+            spec("b.org/lib@1.0.0", &[]),
+            spec("b.org/lib@1.4.0", &[]),
+            spec("c.org/util@2.0.0", &[]),
+            spec("c.org/util@2.1.0", &[]),
+        ]));
+        let roots = vec![
+            Dependency::parse("b.org/lib").unwrap(),
+            Dependency::parse("c.org/util@2.x").unwrap(),
+        ];
+
+        let resolved = Resolver::new(None).resolve(&roots, &source).await.unwrap();
+        let version = |key: &str| resolved.packages[key].dependency.version.clone().unwrap();
+        assert_eq!(version("b.org/lib"), "1.4.0");
+        assert_eq!(version("c.org/util"), "2.1.0");
+
+        let lock = CanonLock::new(&[], vec![spec_lock("b.org", "lib", "1.0.0")]);
+        let locked = Resolver::new(Some(&lock))
+            .resolve(&roots, &source)
+            .await
+            .unwrap();
+        assert_eq!(
+            locked.packages["b.org/lib"].dependency.version.as_deref(),
+            Some("1.0.0")
+        );
+    }
+
+    fn spec_lock(publisher: &str, id: &str, version: &str) -> canon_protocol::LockedPackage {
+        canon_protocol::LockedPackage {
+            publisher: publisher.to_string(),
+            id: id.to_string(),
+            version: version.to_string(),
+            registry: "https://registry.example.com".to_string(),
+            canonical_hash: None,
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::core::registry::{Registry, RegistryLocation};
+use crate::core::resolver::SpecSource;
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::semver::Version;
+use canon_protocol::{compare_versions, is_complete_package, CanonSpecification, Dependency};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A package installed under `.canon/<registry>/<publisher>/<id>/<version>`
+#[derive(Debug, Clone)]
+pub struct InstalledPackage {
+    pub registry: String,
+    pub dependency: Dependency,
+    pub path: PathBuf,
+}
+
+impl InstalledPackage {
+    /// `publisher/id@version`
+    pub fn uri(&self) -> String {
+        self.dependency.to_uri()
+    }
+}
+
+/// List every installed package, sorted by registry, publisher, id and version
+/// (by SemVer precedence)
+///
+/// Hidden directories such as `.canon/.quarantine` are skipped.
+pub fn list_installed(canon_dir: &Path) -> CanonResult<Vec<InstalledPackage>> {
+    let mut packages = Vec::new();
+    if !canon_dir.exists() {
+        return Ok(packages);
+    }
+
+    for registry in subdirectories(canon_dir)? {
+        for publisher in subdirectories(&registry)? {
+            for id in subdirectories(&publisher)? {
+                let mut versions = subdirectories(&id)?;
+                versions.sort_by(|a, b| compare_versions(&file_name(a), &file_name(b)));
+                for version in versions {
+                    packages.push(InstalledPackage {
+                        registry: file_name(&registry),
+                        dependency: Dependency {
+                            publisher: file_name(&publisher),
+                            id: file_name(&id),
+                            version: Some(file_name(&version)),
+                            version_operator: None,
+                        },
+                        path: version,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Versions of `dep`'s package fully installed in `base_dir` for one registry
+pub fn installed_versions(
+    base_dir: &Path,
+    location: &RegistryLocation,
+    dep: &Dependency,
+) -> CanonResult<Vec<Version>> {
+    let dir = base_dir
+        .join(".canon")
+        .join(location.dir_name())
+        .join(&dep.publisher)
+        .join(&dep.id);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    Ok(subdirectories(&dir)?
+        .into_iter()
+        .filter(|path| is_complete_package(path))
+        .filter_map(|path| Version::parse(&file_name(&path)).ok())
+        .collect())
+}
+
+/// Reads specifications from packages already installed for one registry,
+/// never fetching anything
+pub struct InstalledSource<'a> {
+    pub base_dir: &'a Path,
+    pub location: &'a RegistryLocation,
+}
+
+impl SpecSource for InstalledSource<'_> {
+    async fn load(&self, dep: &Dependency) -> CanonResult<CanonSpecification> {
+        let package_dir = self.base_dir.join(self.location.local_path(dep)?);
+        if !is_complete_package(&package_dir) {
+            return Err(CanonError::Command {
+                message: "not installed; run 'canon install'".to_string(),
+            });
+        }
+
+        let canon_yml = package_dir.join("canon.yml");
+        let content = fs::read_to_string(&canon_yml).map_err(CanonError::Io)?;
+        serde_yaml::from_str(&content).map_err(|e| CanonError::ValidationError {
+            message: format!("Failed to parse {}: {}", canon_yml.display(), e),
+        })
+    }
+
+    async fn versions(&self, dep: &Dependency) -> CanonResult<Vec<Version>> {
+        installed_versions(self.base_dir, self.location, dep)
+    }
+}
+
+/// Reads specifications without installing anything: from `.canon/` when
+/// that exact version is there, otherwise straight from the registry
+pub struct PeekSource<'a, R> {
+    pub base_dir: &'a Path,
+    pub location: &'a RegistryLocation,
+    pub registry: &'a R,
+}
+
+impl<R: Registry> SpecSource for PeekSource<'_, R> {
+    async fn load(&self, dep: &Dependency) -> CanonResult<CanonSpecification> {
+        let package_dir = self.base_dir.join(self.location.local_path(dep)?);
+        let content = if is_complete_package(&package_dir) {
+            fs::read(package_dir.join("canon.yml")).map_err(CanonError::Io)?
+        } else {
+            self.registry
+                .fetch(dep, "canon.yml")
+                .await?
+                .ok_or(CanonError::RegistryError {
+                    url: self.location.package_url(dep)?,
+                    status: "not found".to_string(),
+                })?
+        };
+
+        serde_yaml::from_slice(&content).map_err(|e| CanonError::ValidationError {
+            message: format!("Failed to parse canon.yml of {}: {}", dep.to_uri(), e),
+        })
+    }
+
+    async fn versions(&self, dep: &Dependency) -> CanonResult<Vec<Version>> {
+        let versions = self.registry.list_versions(&dep.publisher, &dep.id).await?;
+        Ok(versions
+            .iter()
+            .filter_map(|v| Version::parse(v).ok())
+            .collect())
+    }
+}
+
+/// Non-hidden subdirectories of `dir`, sorted by name
+fn subdirectories(dir: &Path) -> CanonResult<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir).map_err(CanonError::Io)? {
+        let entry = entry.map_err(CanonError::Io)?;
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && entry.file_type().map_err(CanonError::Io)?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
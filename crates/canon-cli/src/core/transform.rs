@@ -0,0 +1,367 @@
+use crate::core::integrity::is_safe_path;
+use crate::utils::{CanonError, CanonResult};
+use canon_protocol::{CanonSpecification, Dependency, SourceDefinition};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Artifact directory inside the package when `output.directory` is not set
+pub const DEFAULT_ARTIFACT_DIR: &str = "artifacts";
+
+/// Template engines available to `canon build`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Engine {
+    Handlebars,
+    Tera,
+}
+
+impl Engine {
+    /// Parse an `--engine` value; `auto` (or nothing) picks per template
+    pub fn parse(name: Option<&str>) -> CanonResult<Option<Self>> {
+        match name.map(str::to_ascii_lowercase).as_deref() {
+            None | Some("auto") => Ok(None),
+            Some(name) => Self::from_name(name)
+                .map(Some)
+                .ok_or_else(|| CanonError::Config {
+                    message: format!(
+                        "Unknown transformation engine '{}' (expected handlebars, tera or auto)",
+                        name
+                    ),
+                }),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "handlebars" | "hbs" => Some(Self::Handlebars),
+            "tera" => Some(Self::Tera),
+            _ => None,
+        }
+    }
+
+    /// Engine implied by a template's extension
+    fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "hbs" | "handlebars" => Some(Self::Handlebars),
+            "tera" => Some(Self::Tera),
+            _ => None,
+        }
+    }
+
+    /// Engine name and the exact version of the crate behind it, for cache keys
+    pub fn cache_id(&self) -> &'static str {
+        match self {
+            Self::Handlebars => concat!("handlebars@", env!("CANON_HANDLEBARS_VERSION")),
+            Self::Tera => concat!("tera@", env!("CANON_TERA_VERSION")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Handlebars => "handlebars",
+            Self::Tera => "tera",
+        }
+    }
+
+    /// Render a template source against a JSON context
+    pub fn render(&self, source: &str, context: &Value) -> Result<String, String> {
+        match self {
+            Self::Handlebars => {
+                let mut handlebars = handlebars::Handlebars::new();
+                // Artifacts are docs and config files, not HTML
+                handlebars.register_escape_fn(handlebars::no_escape);
+                handlebars
+                    .render_template(source, context)
+                    .map_err(|e| error_chain(&e))
+            }
+            Self::Tera => {
+                let context =
+                    tera::Context::from_value(context.clone()).map_err(|e| error_chain(&e))?;
+                tera::Tera::one_off(source, &context, false).map_err(|e| error_chain(&e))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A template to render and the artifact it produces
+#[derive(Debug, Clone)]
+pub struct Template {
+    /// Template file on disk
+    pub source: PathBuf,
+    /// Artifact path relative to the package, e.g. `artifacts/README.md`
+    pub artifact: String,
+    pub engine: Engine,
+}
+
+/// Work out which templates the spec's `sources` declare and where their output goes
+///
+/// Each source directory is walked and filtered by its `include`/`exclude`
+/// globs. The artifact name is the template path with its engine extension
+/// removed, placed under `output.directory`, and kept only if it matches one
+/// of `output.artifacts` (when that list is given). The engine comes from
+/// `engine_override`, else the source's `type`, else the template extension.
+pub fn plan_templates(
+    project_dir: &Path,
+    spec: &CanonSpecification,
+    engine_override: Option<Engine>,
+) -> CanonResult<Vec<Template>> {
+    let output = spec.output().map_err(CanonError::Protocol)?;
+    let output_dir = output
+        .directory
+        .unwrap_or_else(|| DEFAULT_ARTIFACT_DIR.to_string());
+    if !is_safe_path(&output_dir) {
+        return Err(CanonError::Config {
+            message: format!("Invalid output.directory '{}'", output_dir),
+        });
+    }
+    let wanted = output.artifacts.as_deref().map(glob_set).transpose()?;
+
+    let mut templates = Vec::new();
+    for source in spec.sources().map_err(CanonError::Protocol)? {
+        for (path, relative) in source_files(project_dir, &source)? {
+            let engine = engine_override
+                .or_else(|| Engine::from_name(&source.r#type.to_ascii_lowercase()))
+                .or_else(|| Engine::from_extension(&path))
+                .ok_or_else(|| CanonError::TransformationError {
+                    transformation: relative.clone(),
+                    reason: "no engine for this template; set the source type or --engine"
+                        .to_string(),
+                })?;
+
+            let name = strip_template_extension(&relative);
+            if wanted.as_ref().is_some_and(|set| !set.is_match(&name)) {
+                continue;
+            }
+
+            templates.push(Template {
+                source: path,
+                artifact: format!("{}/{}", output_dir, name),
+                engine,
+            });
+        }
+    }
+
+    templates.sort_by(|a, b| a.artifact.cmp(&b.artifact));
+    if let Some(pair) = templates
+        .windows(2)
+        .find(|w| w[0].artifact == w[1].artifact)
+    {
+        return Err(CanonError::TransformationError {
+            transformation: pair[0].artifact.clone(),
+            reason: format!(
+                "produced by both {} and {}",
+                pair[0].source.display(),
+                pair[1].source.display()
+            ),
+        });
+    }
+
+    Ok(templates)
+}
+
+/// Template context: the spec itself and its resolved dependencies
+pub fn build_context(
+    spec: &CanonSpecification,
+    dependencies: &[(Dependency, CanonSpecification)],
+) -> CanonResult<Value> {
+    let dependencies = dependencies
+        .iter()
+        .map(|(dep, dep_spec)| {
+            Ok(json!({
+                "uri": dep.to_uri(),
+                "publisher": dep.publisher,
+                "id": dep.id,
+                "version": dep.version,
+                "spec": to_json(dep_spec)?,
+            }))
+        })
+        .collect::<CanonResult<Vec<_>>>()?;
+
+    Ok(json!({
+        "spec": to_json(spec)?,
+        "dependencies": dependencies,
+    }))
+}
+
+/// Render one template from its already-loaded source
+pub fn render(template: &Template, source: &str, context: &Value) -> CanonResult<String> {
+    template
+        .engine
+        .render(source, context)
+        .map_err(|reason| CanonError::TransformationError {
+            transformation: template.source.display().to_string(),
+            reason,
+        })
+}
+
+/// Files under a source directory that pass its include/exclude globs
+fn source_files(
+    project_dir: &Path,
+    source: &SourceDefinition,
+) -> CanonResult<Vec<(PathBuf, String)>> {
+    if !is_safe_path(&source.path) {
+        return Err(CanonError::Config {
+            message: format!("Invalid source path '{}'", source.path),
+        });
+    }
+    let root = project_dir.join(&source.path);
+    if !root.is_dir() {
+        return Err(CanonError::Config {
+            message: format!("Source directory '{}' does not exist", source.path),
+        });
+    }
+
+    let include = source.include.as_deref().map(glob_set).transpose()?;
+    let exclude = source.exclude.as_deref().map(glob_set).transpose()?;
+
+    let mut files = Vec::new();
+    for entry in WalkDir::new(&root).sort_by_file_name() {
+        let entry = entry.map_err(|e| CanonError::Command {
+            message: format!("Failed to scan {}: {}", root.display(), e),
+        })?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = entry
+            .path()
+            .strip_prefix(&root)
+            .unwrap_or(entry.path())
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if include.as_ref().is_some_and(|set| !set.is_match(&relative))
+            || exclude.as_ref().is_some_and(|set| set.is_match(&relative))
+        {
+            continue;
+        }
+
+        files.push((entry.into_path(), relative));
+    }
+
+    Ok(files)
+}
+
+fn strip_template_extension(path: &str) -> String {
+    [".hbs", ".handlebars", ".tera"]
+        .iter()
+        .find_map(|ext| path.strip_suffix(ext))
+        .unwrap_or(path)
+        .to_string()
+}
+
+fn glob_set(patterns: &[String]) -> CanonResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| CanonError::Config {
+            message: format!("Invalid glob '{}': {}", pattern, e),
+        })?);
+    }
+    builder.build().map_err(|e| CanonError::Config {
+        message: format!("Invalid glob set: {}", e),
+    })
+}
+
+fn to_json(spec: &CanonSpecification) -> CanonResult<Value> {
+    serde_json::to_value(spec).map_err(|e| CanonError::Config {
+        message: format!("Failed to convert specification to template context: {}", e),
+    })
+}
+
+/// Join an error with its sources; template engines put the useful detail there
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn project(
+        sources: &str,
+        output: &str,
+        files: &[&str],
+    ) -> (tempfile::TempDir, CanonSpecification) {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "{{spec.metadata.id}}").unwrap();
+        }
+        let yaml = format!(
+            "canon: \"1.0\"\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: api\n  version: 1.0.0\n  publisher: acme.com\nsources:\n{}{}",
+            sources, output
+        );
+        (dir, serde_yaml::from_str(&yaml).unwrap())
+    }
+
+    fn artifacts(templates: &[Template]) -> Vec<&str> {
+        templates.iter().map(|t| t.artifact.as_str()).collect()
+    }
+
+    #[test]
+    fn test_plan_templates_filters_and_maps_artifacts() {
+        let (dir, spec) = project(
+            "  - path: templates\n    type: handlebars\n    include: [\"**/*.hbs\", \"**/*.tera\"]\n    exclude: [\"drafts/**\"]\n",
+            "output:\n  directory: docs\n  artifacts: [\"*.md\", \"api/**\"]\n",
+            &[
+                "templates/README.md.hbs",
+                "templates/api/index.html.tera",
+                "templates/notes.txt.hbs",
+                "templates/drafts/plan.md.hbs",
+                "templates/raw.md",
+            ],
+        );
+
+        let templates = plan_templates(dir.path(), &spec, None).unwrap();
+        assert_eq!(
+            artifacts(&templates),
+            vec!["docs/README.md", "docs/api/index.html"]
+        );
+        // The source type wins over the extension unless --engine is given
+        assert!(templates.iter().all(|t| t.engine == Engine::Handlebars));
+        let templates = plan_templates(dir.path(), &spec, Some(Engine::Tera)).unwrap();
+        assert!(templates.iter().all(|t| t.engine == Engine::Tera));
+    }
+
+    #[test]
+    fn test_plan_templates_rejects_duplicates_and_unsafe_paths() {
+        let (dir, spec) = project(
+            "  - path: a\n    type: auto\n  - path: b\n    type: auto\n",
+            "",
+            &["a/README.md.hbs", "b/README.md.tera"],
+        );
+        match plan_templates(dir.path(), &spec, None) {
+            Err(CanonError::TransformationError { transformation, .. }) => {
+                assert_eq!(transformation, "artifacts/README.md")
+            }
+            other => panic!("expected duplicate artifact error, got {:?}", other),
+        }
+
+        for path in ["../outside", "/etc"] {
+            let (dir, spec) = project(&format!("  - path: {}\n    type: tera\n", path), "", &[]);
+            let err = plan_templates(dir.path(), &spec, None)
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("Invalid source path"), "{}", err);
+        }
+    }
+}
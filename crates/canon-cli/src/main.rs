@@ -7,7 +7,10 @@ mod config;
 mod core;
 mod utils;
 
-use cli::Cli;
+use cli::{Cli, Commands};
+use config::Config;
+use console::style;
+use std::path::Path;
 use utils::error::CanonResult;
 
 #[tokio::main]
@@ -22,7 +25,19 @@ async fn main() {
 
 async fn run(cli: Cli) -> CanonResult<()> {
     match cli.command {
-        Some(command) => commands::handle_command(command).await,
+        Some(command) => {
+            let config_file = cli.config.as_deref().map(Path::new);
+            // `canon config` must keep working so a bad value can be fixed
+            let config = if matches!(command, Commands::Config { .. }) {
+                Config::load_lenient(config_file, cli.registry.as_deref(), cli.offline)?
+            } else {
+                Config::load(config_file, cli.registry.as_deref(), cli.offline)?
+            };
+            for problem in config.problems() {
+                eprintln!("{} {} (ignored)", style("⚠").yellow(), problem);
+            }
+            commands::handle_command(command, &config).await
+        }
         None => {
             println!("Canon Protocol CLI v{}", env!("CARGO_PKG_VERSION"));
             println!("Transform chaos into structured specifications");
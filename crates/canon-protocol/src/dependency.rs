@@ -1,6 +1,12 @@
 use crate::error::{ProtocolError, ProtocolResult};
+use crate::manifest::is_complete_package;
+use crate::version::{parse_version, parse_version_req, select_version};
+use semver::{Version, VersionReq};
 use std::path::PathBuf;
 
+/// Host of the default public Canon registry
+pub const DEFAULT_REGISTRY: &str = "canon.canon-protocol.org";
+
 /// Represents a parsed dependency URI
 #[derive(Debug, Clone)]
 pub struct Dependency {
@@ -35,6 +41,16 @@ impl Dependency {
             )));
         }
 
+        // Both name directories in the registry and under .canon
+        for (field, segment) in [("publisher", path_parts[0]), ("id", path_parts[1])] {
+            if segment.is_empty() || segment == "." || segment == ".." || segment.contains('\\') {
+                return Err(ProtocolError::InvalidUri(format!(
+                    "Invalid {} '{}' in dependency URI: {}",
+                    field, segment, uri
+                )));
+            }
+        }
+
         let publisher = path_parts[0].to_string();
         let id = path_parts[1].to_string();
 
@@ -54,50 +70,100 @@ impl Dependency {
             (None, None)
         };
 
-        Ok(Self {
+        let dependency = Self {
             publisher,
             id,
             version,
             version_operator,
-        })
+        };
+        // Reject versions that are neither a SemVer version nor a range
+        dependency.version_req()?;
+        Ok(dependency)
+    }
+
+    /// Get the semver requirement described by this dependency's version
+    ///
+    /// A dependency without a version accepts any stable release.
+    pub fn version_req(&self) -> ProtocolResult<VersionReq> {
+        match &self.version {
+            Some(version) => parse_version_req(version, self.version_operator.as_ref()),
+            None => Ok(VersionReq::STAR),
+        }
+    }
+
+    /// Pick the highest of `available` that satisfies this dependency
+    pub fn best_match<'a>(&self, available: &'a [Version]) -> ProtocolResult<Option<&'a Version>> {
+        let req = self.version_req()?;
+        Ok(select_version(&req, available))
+    }
+
+    /// The exact version this dependency is pinned to
+    ///
+    /// Dependencies without a version or with a range have no single place
+    /// to be fetched from or installed to; resolve them against the
+    /// registry's version listing first.
+    pub fn pinned_version(&self) -> ProtocolResult<Version> {
+        let unpinned = || {
+            ProtocolError::InvalidVersion(format!(
+                "{} is not pinned to an exact version",
+                self.to_uri()
+            ))
+        };
+        match (&self.version, &self.version_operator) {
+            (Some(version), None) => parse_version(version).map_err(|_| unpinned()),
+            _ => Err(unpinned()),
+        }
     }
 
-    /// Get the local storage path for this dependency with registry
-    pub fn local_path_with_registry(&self, registry: &str) -> PathBuf {
+    /// Get the local storage path for this pinned dependency with registry
+    pub fn local_path_with_registry(&self, registry: &str) -> ProtocolResult<PathBuf> {
+        let version = self.pinned_version()?;
         let mut path = PathBuf::from(".canon");
         path.push(registry);
         path.push(&self.publisher);
         path.push(&self.id);
-        if let Some(ref version) = self.version {
-            path.push(version);
-        }
-        path
+        path.push(version.to_string());
+        Ok(path)
+    }
+
+    /// Get the local storage path for this pinned dependency (defaults to canon.canon-protocol.org)
+    pub fn local_path(&self) -> ProtocolResult<PathBuf> {
+        self.local_path_with_registry(DEFAULT_REGISTRY)
     }
 
-    /// Get the local storage path for this dependency (defaults to canon.canon-protocol.org)
-    pub fn local_path(&self) -> PathBuf {
-        self.local_path_with_registry("canon.canon-protocol.org")
+    /// Base URL of this pinned dependency's package on a registry, ending in `/`
+    pub fn package_url(&self, registry_url: &str) -> ProtocolResult<String> {
+        let version = self.pinned_version()?;
+        Ok(format!(
+            "{}/{}/{}/{}/",
+            registry_url.trim_end_matches('/'),
+            self.publisher,
+            self.id,
+            version
+        ))
     }
 
     /// Construct the URL for fetching from canon.canon-protocol.org
-    pub fn canon_url(&self) -> String {
-        let version = self.version.as_deref().unwrap_or("latest");
-        format!(
-            "https://canon.canon-protocol.org/{}/{}/{}/canon.yml",
-            self.publisher, self.id, version
-        )
+    pub fn canon_url(&self) -> ProtocolResult<String> {
+        Ok(format!(
+            "{}canon.yml",
+            self.package_url(&format!("https://{}", DEFAULT_REGISTRY))?
+        ))
     }
 
-    /// Check if this dependency exists in localhost
+    /// Check if this dependency has been built into localhost
     pub fn is_in_localhost(&self) -> bool {
-        let canon_file = self.local_path_with_registry("localhost").join("canon.yml");
-        canon_file.exists()
+        self.local_path_with_registry("localhost")
+            .is_ok_and(|path| is_complete_package(&path))
     }
 
-    /// Check if this dependency is already installed
+    /// Check if this dependency is fully installed from the default registry
+    ///
+    /// A directory holding only part of the package, e.g. canon.yml without
+    /// the files its manifest lists, does not count.
     pub fn is_installed(&self) -> bool {
-        let canon_file = self.local_path().join("canon.yml");
-        canon_file.exists()
+        self.local_path()
+            .is_ok_and(|path| is_complete_package(&path))
     }
 
     /// Format the dependency as a URI string
@@ -134,6 +200,30 @@ mod tests {
         assert_eq!(dep_no_version.version_operator, None);
     }
 
+    #[test]
+    fn test_parse_prerelease_dependency() {
+        let prerelease = Dependency::parse("example.com/api@1.0.0-beta.1+build.5").unwrap();
+        assert_eq!(prerelease.version.as_deref(), Some("1.0.0-beta.1+build.5"));
+        assert_eq!(prerelease.pinned_version().unwrap().pre.as_str(), "beta.1");
+        assert!(Dependency::parse("example.com/api@not-a-version").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_unsafe_segments() {
+        for uri in [
+            "/api@1.0.0",
+            "example.com/@1.0.0",
+            "../api@1.0.0",
+            "example.com/.",
+            "example.com/..@1.0.0",
+            "example.com/a\\b@1.0.0",
+            "example.com/a/b@1.0.0",
+        ] {
+            assert!(Dependency::parse(uri).is_err(), "{}", uri);
+        }
+        assert!(Dependency::parse("example.com/api.v2@1.0.0").is_ok());
+    }
+
     #[test]
     fn test_parse_dependency_with_operators() {
         let dep_caret = Dependency::parse("profiles.org/author@^1.0.0").unwrap();
@@ -158,7 +248,7 @@ mod tests {
             version_operator: None,
         };
 
-        let path = dep.local_path();
+        let path = dep.local_path().unwrap();
         assert_eq!(
             path,
             PathBuf::from(".canon/canon.canon-protocol.org/canon-protocol.org/type/1.0.0")
@@ -174,13 +264,31 @@ mod tests {
             version_operator: None,
         };
 
-        let url = dep.canon_url();
+        let url = dep.canon_url().unwrap();
         assert_eq!(
             url,
             "https://canon.canon-protocol.org/canon-protocol.org/type/1.0.0/canon.yml"
         );
     }
 
+    #[test]
+    fn test_package_url() {
+        let dep = Dependency::parse("canon-protocol.org/type@1.0.0").unwrap();
+        assert_eq!(
+            dep.package_url("https://registry.example.com:8443/")
+                .unwrap(),
+            "https://registry.example.com:8443/canon-protocol.org/type/1.0.0/"
+        );
+    }
+
+    #[test]
+    fn test_unpinned_dependencies_have_no_url() {
+        // Ranges and missing versions name no single package
+        for uri in ["canon-protocol.org/type", "canon-protocol.org/type@^1.0.0"] {
+            assert!(Dependency::parse(uri).unwrap().canon_url().is_err());
+        }
+    }
+
     #[test]
     fn test_to_uri() {
         let dep = Dependency {
@@ -199,4 +307,30 @@ mod tests {
         };
         assert_eq!(dep_caret.to_uri(), "profiles.org/author@^1.0.0");
     }
+
+    #[test]
+    fn test_best_match() {
+        let available: Vec<Version> = ["1.0.0", "1.2.0", "2.0.0"]
+            .iter()
+            .map(|v| Version::parse(v).unwrap())
+            .collect();
+
+        let dep = Dependency::parse("profiles.org/author@^1.0.0").unwrap();
+        assert_eq!(
+            dep.best_match(&available).unwrap(),
+            Some(&Version::new(1, 2, 0))
+        );
+
+        let exact = Dependency::parse("profiles.org/author@1.0.0").unwrap();
+        assert_eq!(
+            exact.best_match(&available).unwrap(),
+            Some(&Version::new(1, 0, 0))
+        );
+
+        let any = Dependency::parse("profiles.org/author").unwrap();
+        assert_eq!(
+            any.best_match(&available).unwrap(),
+            Some(&Version::new(2, 0, 0))
+        );
+    }
 }
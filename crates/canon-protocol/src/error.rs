@@ -5,9 +5,15 @@ pub enum ProtocolError {
     #[error("Invalid URI format: {0}")]
     InvalidUri(String),
 
+    #[error("Invalid version: {0}")]
+    InvalidVersion(String),
+
     #[error("Invalid specification: {0}")]
     InvalidSpecification(String),
 
+    #[error("Signature error: {0}")]
+    SignatureError(String),
+
     #[error("Validation failed: {0}")]
     ValidationError(String),
 
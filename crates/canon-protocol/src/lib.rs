@@ -5,16 +5,28 @@
 
 pub mod dependency;
 pub mod error;
+pub mod lockfile;
 pub mod manifest;
 pub mod signature;
 pub mod specification;
+pub mod version;
 
 // Re-export commonly used types at the crate root
-pub use dependency::{Dependency, VersionOperator};
+pub use dependency::{Dependency, VersionOperator, DEFAULT_REGISTRY};
 pub use error::{ProtocolError, ProtocolResult};
-pub use manifest::{CanonManifest, ManifestFile, ManifestSpecification};
-pub use signature::{CanonSignature, PublisherKeys, SignatureData};
+pub use lockfile::{CanonLock, LockedPackage};
+pub use manifest::{
+    hash_contents, is_complete_package, CanonManifest, ManifestFile, ManifestSpecification,
+};
+pub use signature::{
+    public_key_to_base64, signing_key_from_base64, CanonSignature, PublisherKey, PublisherKeys,
+    RevokedKey, SignatureData,
+};
 pub use specification::{
     CanonSpecification, FieldType, OutputConfiguration, SchemaField, SourceDefinition,
     SpecificationMetadata,
 };
+pub use version::{compare_versions, parse_version, parse_version_req, select_version};
+
+// Re-export semver so consumers don't need a matching direct dependency
+pub use semver;
@@ -0,0 +1,142 @@
+use crate::dependency::Dependency;
+use crate::error::ProtocolResult;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Canon lockfile (`canon.lock`)
+///
+/// Records the exact version, source registry and manifest hash of every
+/// installed dependency so later installs reproduce the same set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanonLock {
+    pub lock_version: String,
+
+    /// Dependency URIs exactly as declared in canon.yml when the lock was written
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+
+    /// Resolved packages, sorted by publisher and id
+    #[serde(default)]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub publisher: String,
+    pub id: String,
+    pub version: String,
+    pub registry: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canonical_hash: Option<String>,
+}
+
+impl CanonLock {
+    /// File name of the lockfile, next to canon.yml
+    pub const FILE_NAME: &'static str = "canon.lock";
+
+    /// Create a lock for the given declared dependencies
+    pub fn new(dependencies: &[String], mut packages: Vec<LockedPackage>) -> Self {
+        let mut dependencies = dependencies.to_vec();
+        dependencies.sort();
+        dependencies.dedup();
+        packages.sort_by(|a, b| (&a.publisher, &a.id).cmp(&(&b.publisher, &b.id)));
+
+        Self {
+            lock_version: "1".to_string(),
+            dependencies,
+            packages,
+        }
+    }
+
+    /// Load a lockfile, returning `None` if it does not exist
+    pub fn load(path: &Path) -> ProtocolResult<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(Some(serde_yaml::from_str(&content)?))
+    }
+
+    /// Write the lockfile to disk
+    pub fn save(&self, path: &Path) -> ProtocolResult<()> {
+        let content = serde_yaml::to_string(self)?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Check whether the lock was written for exactly these declared dependencies
+    pub fn is_in_sync(&self, dependencies: &[String]) -> bool {
+        let mut declared = dependencies.to_vec();
+        declared.sort();
+        declared.dedup();
+        declared == self.dependencies
+    }
+
+    /// Find the locked package for a dependency (matched by publisher and id)
+    pub fn find(&self, dep: &Dependency) -> Option<&LockedPackage> {
+        self.packages
+            .iter()
+            .find(|p| p.publisher == dep.publisher && p.id == dep.id)
+    }
+}
+
+impl LockedPackage {
+    /// The exact dependency this locked package pins
+    pub fn to_dependency(&self) -> Dependency {
+        Dependency {
+            publisher: self.publisher.clone(),
+            id: self.id.clone(),
+            version: Some(self.version.clone()),
+            version_operator: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(id: &str, version: &str) -> LockedPackage {
+        LockedPackage {
+            publisher: "canon-protocol.org".to_string(),
+            id: id.to_string(),
+            version: version.to_string(),
+            registry: "https://canon.canon-protocol.org".to_string(),
+            canonical_hash: Some("sha256:abc".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_lock_sync_ignores_order() {
+        let declared = vec![
+            "profiles.org/author@^1.0.0".to_string(),
+            "canon-protocol.org/type@1.0.0".to_string(),
+        ];
+        let lock = CanonLock::new(&declared, vec![package("type", "1.0.0")]);
+
+        let reordered = vec![declared[1].clone(), declared[0].clone()];
+        assert!(lock.is_in_sync(&reordered));
+        assert!(!lock.is_in_sync(&declared[..1]));
+    }
+
+    #[test]
+    fn test_lock_roundtrip_and_find() {
+        let lock = CanonLock::new(
+            &["canon-protocol.org/type@1.0.0".to_string()],
+            vec![package("type", "1.0.0"), package("project", "1.0.0")],
+        );
+
+        let yaml = serde_yaml::to_string(&lock).unwrap();
+        let parsed: CanonLock = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed, lock);
+        assert_eq!(parsed.packages[0].id, "project");
+
+        let dep = Dependency::parse("canon-protocol.org/type@^1.0.0").unwrap();
+        let locked = parsed.find(&dep).unwrap();
+        assert_eq!(
+            locked.to_dependency().to_uri(),
+            "canon-protocol.org/type@1.0.0"
+        );
+    }
+}
@@ -1,9 +1,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 /// Canon Protocol Manifest
 /// Conforms to canon-protocol.org/manifest@1.0.0
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CanonManifest {
     pub canon: String,
     pub manifest_version: String,
@@ -17,7 +18,7 @@ pub struct CanonManifest {
     pub canonical_hash: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ManifestSpecification {
     pub id: String,
     pub version: String,
@@ -33,6 +34,9 @@ pub struct ManifestFile {
 }
 
 impl CanonManifest {
+    /// File name of the manifest inside a package
+    pub const FILE_NAME: &'static str = "canon-manifest.yml";
+
     /// Create a new manifest
     pub fn new(spec: ManifestSpecification, files: Vec<ManifestFile>) -> Self {
         let total_size = files.iter().map(|f| f.size).sum();
@@ -69,3 +73,48 @@ impl CanonManifest {
         format!("sha256:{:x}", hasher.finalize())
     }
 }
+
+impl ManifestFile {
+    /// Describe a file from its package-relative path and contents
+    pub fn from_contents(path: &str, contents: &[u8]) -> Self {
+        Self {
+            path: path.to_string(),
+            size: contents.len() as u64,
+            hash: hash_contents(contents),
+        }
+    }
+}
+
+/// SHA-256 of file contents in the manifest's `sha256:<hex>` form
+pub fn hash_contents(contents: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    format!("sha256:{:x}", Sha256::digest(contents))
+}
+
+/// Check that `dir` holds a complete package
+///
+/// canon.yml must be present and, when the package has a manifest, so must
+/// every file the manifest lists. Legacy packages without a manifest only
+/// need canon.yml.
+pub fn is_complete_package(dir: &Path) -> bool {
+    if !dir.join("canon.yml").is_file() {
+        return false;
+    }
+
+    let manifest_path = dir.join(CanonManifest::FILE_NAME);
+    if !manifest_path.exists() {
+        return true;
+    }
+
+    let Ok(content) = std::fs::read_to_string(&manifest_path) else {
+        return false;
+    };
+    match serde_yaml::from_str::<CanonManifest>(&content) {
+        Ok(manifest) => manifest
+            .files
+            .iter()
+            .all(|file| dir.join(&file.path).is_file()),
+        Err(_) => false,
+    }
+}
@@ -1,8 +1,21 @@
-use chrono::{DateTime, Utc};
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::manifest::CanonManifest;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
 use serde::{Deserialize, Serialize};
 
+/// The only signature algorithm defined by the protocol
+pub const ED25519: &str = "ed25519";
+
+/// Signatures over the manifest hash, key id and signing time together
+pub const SIGNATURE_VERSION: &str = "1.1";
+
+/// How far a signature's `signed_at` may be ahead of the verifier's clock
+const MAX_CLOCK_SKEW_SECONDS: i64 = 5 * 60;
+
 /// Canon signature for verifying authenticity
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CanonSignature {
     pub canon: String,
     pub signature_version: String,
@@ -10,7 +23,7 @@ pub struct CanonSignature {
     pub signature: SignatureData,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignatureData {
     pub algorithm: String,
     pub key_id: String,
@@ -19,7 +32,7 @@ pub struct SignatureData {
 }
 
 /// Publisher keys for verification
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublisherKeys {
     pub version: String,
     pub keys: std::collections::HashMap<String, PublisherKey>,
@@ -27,7 +40,7 @@ pub struct PublisherKeys {
     pub revoked_keys: std::collections::HashMap<String, RevokedKey>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublisherKey {
     pub algorithm: String,
     pub public_key: String,
@@ -37,8 +50,389 @@ pub struct PublisherKey {
     pub usage: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RevokedKey {
     pub revoked_at: DateTime<Utc>,
     pub reason: String,
 }
+
+impl CanonSignature {
+    /// File name of the signature inside a package
+    pub const FILE_NAME: &'static str = "canon-signature.yml";
+
+    /// Sign a manifest's canonical hash with an Ed25519 key
+    ///
+    /// The key id and signing time are signed along with the hash, so
+    /// neither can be changed without invalidating the signature.
+    pub fn sign(manifest: &CanonManifest, key_id: &str, signing_key: &SigningKey) -> Self {
+        let mut signature = Self {
+            canon: "1.0".to_string(),
+            signature_version: SIGNATURE_VERSION.to_string(),
+            manifest_hash: manifest.canonical_hash.clone(),
+            signature: SignatureData {
+                algorithm: ED25519.to_string(),
+                key_id: key_id.to_string(),
+                signature: String::new(),
+                signed_at: Utc::now(),
+            },
+        };
+        let bytes = signing_key.sign(&signature.signed_payload());
+        signature.signature.signature = BASE64.encode(bytes.to_bytes());
+        signature
+    }
+
+    /// The bytes the Ed25519 signature covers
+    fn signed_payload(&self) -> Vec<u8> {
+        format!(
+            "canon-signature/{}\n{}\n{}\n{}",
+            self.signature_version,
+            self.manifest_hash,
+            self.signature.key_id,
+            self.signature
+                .signed_at
+                .to_rfc3339_opts(SecondsFormat::Nanos, true)
+        )
+        .into_bytes()
+    }
+
+    /// Verify this signature over `manifest` against the publisher's keys
+    pub fn verify(&self, manifest: &CanonManifest, keys: &PublisherKeys) -> ProtocolResult<()> {
+        self.verify_at(manifest, keys, Utc::now())
+    }
+
+    /// Verify as of the given time
+    ///
+    /// Checks that the manifest is internally consistent and matches the
+    /// signed hash, that the key is known, unrevoked, meant for signing,
+    /// existed when the signature was made and has not expired by `now`,
+    /// and that the Ed25519 signature itself is correct.
+    pub fn verify_at(
+        &self,
+        manifest: &CanonManifest,
+        keys: &PublisherKeys,
+        now: DateTime<Utc>,
+    ) -> ProtocolResult<()> {
+        let computed = CanonManifest::compute_canonical_hash(&manifest.files);
+        if computed != manifest.canonical_hash {
+            return Err(signature_error(format!(
+                "manifest canonical_hash {} does not match its files ({})",
+                manifest.canonical_hash, computed
+            )));
+        }
+
+        if self.manifest_hash != manifest.canonical_hash {
+            return Err(signature_error(format!(
+                "signature covers {} but manifest hash is {}",
+                self.manifest_hash, manifest.canonical_hash
+            )));
+        }
+
+        if self.signature_version != SIGNATURE_VERSION {
+            return Err(signature_error(format!(
+                "unsupported signature_version '{}'",
+                self.signature_version
+            )));
+        }
+
+        let data = &self.signature;
+        if !data.algorithm.eq_ignore_ascii_case(ED25519) {
+            return Err(signature_error(format!(
+                "unsupported algorithm '{}'",
+                data.algorithm
+            )));
+        }
+
+        let key_id = &data.key_id;
+        if let Some(revoked) = keys.revoked_keys.get(key_id) {
+            return Err(signature_error(format!(
+                "key '{}' was revoked at {}: {}",
+                key_id, revoked.revoked_at, revoked.reason
+            )));
+        }
+
+        let key = keys
+            .keys
+            .get(key_id)
+            .ok_or_else(|| signature_error(format!("unknown key '{}'", key_id)))?;
+        key.check_valid_for(key_id, data.signed_at, now)?;
+
+        let verifying_key = key.verifying_key()?;
+        let signature = decode_signature(&data.signature)?;
+        verifying_key
+            .verify_strict(&self.signed_payload(), &signature)
+            .map_err(|_| signature_error("signature does not match manifest hash".to_string()))
+    }
+}
+
+impl PublisherKeys {
+    /// Well-known path of a publisher's keys document on its own domain
+    pub const WELL_KNOWN_PATH: &'static str = "/.well-known/canon-keys.yml";
+
+    /// URL of the keys document for a publisher domain
+    pub fn url_for(publisher: &str) -> String {
+        format!("https://{}{}", publisher, Self::WELL_KNOWN_PATH)
+    }
+}
+
+impl PublisherKey {
+    /// Check revocation, usage and validity window for a signature made at `signed_at`
+    ///
+    /// Expiry is checked against `now`, not `signed_at`: whoever holds an
+    /// expired key can sign with any date they like. `signed_at` may be a
+    /// few minutes ahead of `now`, for publishers whose clock runs fast.
+    fn check_valid_for(
+        &self,
+        key_id: &str,
+        signed_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> ProtocolResult<()> {
+        if self.revoked {
+            return Err(signature_error(format!("key '{}' is revoked", key_id)));
+        }
+
+        if !self.algorithm.eq_ignore_ascii_case(ED25519) {
+            return Err(signature_error(format!(
+                "key '{}' uses unsupported algorithm '{}'",
+                key_id, self.algorithm
+            )));
+        }
+
+        // Usage may list several purposes, e.g. "signing, encryption"
+        if !self
+            .usage
+            .split([',', ' '])
+            .any(|u| u.eq_ignore_ascii_case("signing"))
+        {
+            return Err(signature_error(format!(
+                "key '{}' is not authorized for signing (usage: {})",
+                key_id, self.usage
+            )));
+        }
+
+        if signed_at > now + chrono::Duration::seconds(MAX_CLOCK_SKEW_SECONDS) {
+            return Err(signature_error(format!(
+                "signature date {} is in the future",
+                signed_at
+            )));
+        }
+
+        if signed_at < self.created_at {
+            return Err(signature_error(format!(
+                "signature predates key '{}' (created {})",
+                key_id, self.created_at
+            )));
+        }
+
+        if let Some(expires_at) = self.expires_at {
+            if now > expires_at {
+                return Err(signature_error(format!(
+                    "key '{}' expired at {}",
+                    key_id, expires_at
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decode the base64-encoded Ed25519 public key
+    pub fn verifying_key(&self) -> ProtocolResult<VerifyingKey> {
+        let bytes: [u8; 32] = decode_fixed(&self.public_key, "public key")?;
+        VerifyingKey::from_bytes(&bytes)
+            .map_err(|e| signature_error(format!("invalid public key: {}", e)))
+    }
+}
+
+/// Parse a base64-encoded 32-byte Ed25519 private key (seed)
+pub fn signing_key_from_base64(encoded: &str) -> ProtocolResult<SigningKey> {
+    let bytes: [u8; 32] = decode_fixed(encoded, "private key")?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Base64-encode the public half of a signing key, as stored in `PublisherKey`
+pub fn public_key_to_base64(signing_key: &SigningKey) -> String {
+    BASE64.encode(signing_key.verifying_key().to_bytes())
+}
+
+fn decode_signature(encoded: &str) -> ProtocolResult<Signature> {
+    let bytes: [u8; 64] = decode_fixed(encoded, "signature")?;
+    Ok(Signature::from_bytes(&bytes))
+}
+
+fn decode_fixed<const N: usize>(encoded: &str, what: &str) -> ProtocolResult<[u8; N]> {
+    let bytes = BASE64
+        .decode(encoded.trim())
+        .map_err(|e| signature_error(format!("invalid base64 {}: {}", what, e)))?;
+    bytes.try_into().map_err(|b: Vec<u8>| {
+        signature_error(format!("{} must be {} bytes, got {}", what, N, b.len()))
+    })
+}
+
+fn signature_error(message: String) -> ProtocolError {
+    ProtocolError::SignatureError(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{ManifestFile, ManifestSpecification};
+    use chrono::Duration;
+    use std::collections::HashMap;
+
+    fn manifest() -> CanonManifest {
+        CanonManifest::new(
+            ManifestSpecification {
+                id: "type".to_string(),
+                version: "1.0.0".to_string(),
+                publisher: "canon-protocol.org".to_string(),
+                r#type: "canon-protocol.org/type@1.0.0".to_string(),
+            },
+            vec![ManifestFile {
+                path: "canon.yml".to_string(),
+                size: 10,
+                hash: "sha256:abc".to_string(),
+            }],
+        )
+    }
+
+    fn keys(signing_key: &SigningKey) -> PublisherKeys {
+        PublisherKeys {
+            version: "1.0".to_string(),
+            keys: HashMap::from([(
+                "key-1".to_string(),
+                PublisherKey {
+                    algorithm: "ed25519".to_string(),
+                    public_key: public_key_to_base64(signing_key),
+                    created_at: Utc::now() - Duration::days(1),
+                    expires_at: None,
+                    revoked: false,
+                    usage: "signing".to_string(),
+                },
+            )]),
+            revoked_keys: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let manifest = manifest();
+        let keys = keys(&signing_key);
+
+        let signature = CanonSignature::sign(&manifest, "key-1", &signing_key);
+        assert!(signature.verify(&manifest, &keys).is_ok());
+
+        let other_key = SigningKey::from_bytes(&[8u8; 32]);
+        let forged = CanonSignature::sign(&manifest, "key-1", &other_key);
+        assert!(forged.verify(&manifest, &keys).is_err());
+
+        let mut tampered = manifest;
+        tampered.files[0].hash = "sha256:def".to_string();
+        tampered.canonical_hash = CanonManifest::compute_canonical_hash(&tampered.files);
+        assert!(signature.verify(&tampered, &keys).is_err());
+    }
+
+    #[test]
+    fn test_key_policy() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let manifest = manifest();
+        let signature = CanonSignature::sign(&manifest, "key-1", &signing_key);
+
+        let mut expired = keys(&signing_key);
+        expired.keys.get_mut("key-1").unwrap().expires_at = Some(Utc::now() - Duration::hours(1));
+        assert!(signature.verify(&manifest, &expired).is_err());
+
+        let mut wrong_usage = keys(&signing_key);
+        wrong_usage.keys.get_mut("key-1").unwrap().usage = "encryption".to_string();
+        assert!(signature.verify(&manifest, &wrong_usage).is_err());
+
+        let mut revoked = keys(&signing_key);
+        revoked.revoked_keys.insert(
+            "key-1".to_string(),
+            RevokedKey {
+                revoked_at: Utc::now(),
+                reason: "compromised".to_string(),
+            },
+        );
+        assert!(signature.verify(&manifest, &revoked).is_err());
+    }
+
+    #[test]
+    fn test_backdated_signature_on_expired_key_is_rejected() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let manifest = manifest();
+        let mut keys = keys(&signing_key);
+        let key = keys.keys.get_mut("key-1").unwrap();
+        key.created_at = Utc::now() - Duration::days(30);
+        key.expires_at = Some(Utc::now() - Duration::days(10));
+        let during_validity = Utc::now() - Duration::days(20);
+
+        // Re-dating an existing signature breaks it
+        let mut redated = CanonSignature::sign(&manifest, "key-1", &signing_key);
+        redated.signature.signed_at = during_validity;
+        let err = redated.verify(&manifest, &keys).unwrap_err().to_string();
+        assert!(err.contains("expired"), "{}", err);
+
+        // Signing afresh with a backdated time still fails on expiry
+        let mut backdated = CanonSignature::sign(&manifest, "key-1", &signing_key);
+        backdated.signature.signed_at = during_validity;
+        let payload = backdated.signed_payload();
+        backdated.signature.signature = BASE64.encode(signing_key.sign(&payload).to_bytes());
+        let err = backdated.verify(&manifest, &keys).unwrap_err().to_string();
+        assert!(err.contains("expired"), "{}", err);
+
+        // While the key is valid, a re-dated signature no longer matches
+        let valid = self::keys(&signing_key);
+        let mut redated = CanonSignature::sign(&manifest, "key-1", &signing_key);
+        redated.signature.signed_at -= Duration::minutes(5);
+        assert!(redated.verify(&manifest, &valid).is_err());
+    }
+
+    #[test]
+    fn test_signature_from_a_fast_clock() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let manifest = manifest();
+        let keys = keys(&signing_key);
+        let signature = CanonSignature::sign(&manifest, "key-1", &signing_key);
+        let signed_at = signature.signature.signed_at;
+
+        assert!(signature
+            .verify_at(&manifest, &keys, signed_at - Duration::minutes(4))
+            .is_ok());
+        let err = signature
+            .verify_at(&manifest, &keys, signed_at - Duration::minutes(6))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("in the future"), "{}", err);
+    }
+
+    #[test]
+    fn test_unbound_signature_versions_are_rejected() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let manifest = manifest();
+        let keys = keys(&signing_key);
+
+        // A "1.0" signature over the hash alone, with nothing else bound in
+        let mut unbound = CanonSignature::sign(&manifest, "key-1", &signing_key);
+        unbound.signature_version = "1.0".to_string();
+        unbound.signature.signature = BASE64.encode(
+            signing_key
+                .sign(manifest.canonical_hash.as_bytes())
+                .to_bytes(),
+        );
+        let err = unbound.verify(&manifest, &keys).unwrap_err().to_string();
+        assert!(
+            err.contains("unsupported signature_version '1.0'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_signing_key_from_base64() {
+        let encoded = BASE64.encode([7u8; 32]);
+        let key = signing_key_from_base64(&encoded).unwrap();
+        assert_eq!(key.to_bytes(), [7u8; 32]);
+        assert!(signing_key_from_base64("c2hvcnQ=").is_err());
+    }
+}
@@ -1,5 +1,6 @@
+use crate::error::ProtocolResult;
 use serde::{Deserialize, Serialize};
-use serde_yaml::Value;
+use serde_yaml::{Mapping, Value};
 use std::collections::HashMap;
 
 /// Canon Protocol Specification
@@ -35,13 +36,110 @@ pub struct CanonSpecification {
     pub content: HashMap<String, Value>,
 }
 
+impl CanonSpecification {
+    /// Specification URIs this specification depends on: its `includes`
+    /// followed by the `uri` of every `ref` field in its schema (including
+    /// nested properties and array items), without duplicates
+    pub fn references(&self) -> Vec<String> {
+        let mut refs: Vec<String> = Vec::new();
+
+        for include in self.includes.iter().flatten() {
+            if !refs.contains(include) {
+                refs.push(include.clone());
+            }
+        }
+
+        if let Some(schema) = &self.schema {
+            // Sort field names so the order is stable across runs
+            let mut names: Vec<&String> = schema.keys().collect();
+            names.sort();
+            for name in names {
+                schema[name].collect_refs(&mut refs);
+            }
+        }
+
+        refs
+    }
+
+    /// Dependency URIs listed in the `dependencies` sequence
+    pub fn dependencies(&self) -> Vec<String> {
+        self.content
+            .get("dependencies")
+            .and_then(|v| v.as_sequence())
+            .map(|seq| {
+                seq.iter()
+                    .filter_map(|v| v.as_str())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Legacy `sources` declarations (template directories for build)
+    pub fn sources(&self) -> ProtocolResult<Vec<SourceDefinition>> {
+        match self.content.get("sources") {
+            Some(value) => Ok(serde_yaml::from_value(value.clone())?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Legacy `output` configuration (where build writes artifacts)
+    pub fn output(&self) -> ProtocolResult<OutputConfiguration> {
+        match self.content.get("output") {
+            Some(value) => Ok(serde_yaml::from_value(value.clone())?),
+            None => Ok(OutputConfiguration::default()),
+        }
+    }
+
+    /// Serialize in canonical form
+    ///
+    /// Protocol fields come first in a fixed order (`canon`, `type`,
+    /// `metadata`, `includes`, `schema`), followed by type-specific content
+    /// sorted by key. Nested maps are sorted too, so the same specification
+    /// always produces byte-identical YAML.
+    pub fn to_canonical_yaml(&self) -> ProtocolResult<String> {
+        let mut root = Mapping::new();
+        root.insert("canon".into(), Value::String(self.canon.clone()));
+        root.insert("type".into(), Value::String(self.r#type.clone()));
+        // Metadata is a struct, so its declared field order is already stable
+        root.insert("metadata".into(), serde_yaml::to_value(&self.metadata)?);
+        if let Some(includes) = &self.includes {
+            root.insert("includes".into(), serde_yaml::to_value(includes)?);
+        }
+        if let Some(schema) = &self.schema {
+            root.insert("schema".into(), sorted(serde_yaml::to_value(schema)?));
+        }
+
+        let mut keys: Vec<&String> = self.content.keys().collect();
+        keys.sort();
+        for key in keys {
+            root.insert(key.as_str().into(), sorted(self.content[key].clone()));
+        }
+
+        Ok(serde_yaml::to_string(&Value::Mapping(root))?)
+    }
+}
+
+/// Recursively sort mapping keys
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Mapping(mapping) => {
+            let mut entries: Vec<(Value, Value)> = mapping.into_iter().collect();
+            entries.sort_by_key(|(k, _)| serde_yaml::to_string(k).unwrap_or_default());
+            Value::Mapping(entries.into_iter().map(|(k, v)| (k, sorted(v))).collect())
+        }
+        Value::Sequence(items) => Value::Sequence(items.into_iter().map(sorted).collect()),
+        other => other,
+    }
+}
+
 /// Required metadata for all specifications
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpecificationMetadata {
     /// Unique identifier (lowercase, alphanumeric with hyphens)
     pub id: String,
 
-    /// Semantic version (MAJOR.MINOR.PATCH)
+    /// Semantic version (SemVer 2.0, e.g. `1.2.0` or `2.0.0-beta.1+build.5`)
     pub version: String,
 
     /// Publisher domain or subdomain
@@ -91,6 +189,28 @@ pub struct SchemaField {
     pub description: Option<String>,
 }
 
+impl SchemaField {
+    fn collect_refs(&self, refs: &mut Vec<String>) {
+        if let (FieldType::Ref, Some(uri)) = (&self.r#type, &self.uri) {
+            if !refs.contains(uri) {
+                refs.push(uri.clone());
+            }
+        }
+
+        if let Some(properties) = &self.properties {
+            let mut names: Vec<&String> = properties.keys().collect();
+            names.sort();
+            for name in names {
+                properties[name].collect_refs(refs);
+            }
+        }
+
+        if let Some(items) = &self.items {
+            items.collect_refs(refs);
+        }
+    }
+}
+
 /// Supported field types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -105,16 +225,90 @@ pub enum FieldType {
 }
 
 // Keep for backward compatibility during migration
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OutputConfiguration {
     pub artifacts: Option<Vec<String>>,
     pub directory: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SourceDefinition {
     pub path: String,
     pub r#type: String,
     pub include: Option<Vec<String>>,
     pub exclude: Option<Vec<String>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references() {
+        let spec: CanonSpecification = serde_yaml::from_str(
+            r#"
+canon: "1.0"
+type: canon-protocol.org/type@1.0.0
+metadata:
+  id: blog-post
+  version: 1.0.0
+  publisher: content.org
+includes:
+  - standards.org/metadata@~2.1.0
+schema:
+  author:
+    type: ref
+    uri: profiles.org/author@^1.0.0
+  tags:
+    type: array
+    items:
+      type: ref
+      uri: content.org/tag@1.0.0
+  meta:
+    type: object
+    properties:
+      editor:
+        type: ref
+        uri: profiles.org/author@^1.0.0
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            spec.references(),
+            vec![
+                "standards.org/metadata@~2.1.0",
+                "profiles.org/author@^1.0.0",
+                "content.org/tag@1.0.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_canonical_yaml() {
+        let spec: CanonSpecification = serde_yaml::from_str(
+            r#"
+zeta:
+  b: 2
+  a:
+    - { y: 1, x: 2 }
+alpha: first
+metadata:
+  version: 1.0.0
+  publisher: content.org
+  id: blog-post
+type: canon-protocol.org/type@1.0.0
+canon: "1.0"
+"#,
+        )
+        .unwrap();
+
+        let canonical = spec.to_canonical_yaml().unwrap();
+        assert_eq!(
+            canonical,
+            "canon: '1.0'\ntype: canon-protocol.org/type@1.0.0\nmetadata:\n  id: blog-post\n  version: 1.0.0\n  publisher: content.org\nalpha: first\nzeta:\n  a:\n  - x: 2\n    y: 1\n  b: 2\n"
+        );
+        let reparsed: CanonSpecification = serde_yaml::from_str(&canonical).unwrap();
+        assert_eq!(reparsed.to_canonical_yaml().unwrap(), canonical);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::dependency::VersionOperator;
+use crate::error::{ProtocolError, ProtocolResult};
+use semver::{Version, VersionReq};
+use std::cmp::Ordering;
+
+/// Parse a SemVer 2.0 version such as `1.2.3`, `1.0.0-beta.1` or `1.0.0+build.5`
+pub fn parse_version(version: &str) -> ProtocolResult<Version> {
+    Version::parse(version.trim()).map_err(|e| {
+        ProtocolError::InvalidVersion(format!(
+            "'{}' is not a semantic version (MAJOR.MINOR.PATCH[-PRERELEASE][+BUILD]): {}",
+            version, e
+        ))
+    })
+}
+
+/// Order version strings by SemVer precedence, e.g. `1.0.0-beta.2` before
+/// `1.0.0-beta.11` before `1.0.0`; strings that are not versions sort first
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Greater,
+        (Err(_), Ok(_)) => Ordering::Less,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// Build a semver requirement from a Canon version constraint
+///
+/// Supported forms:
+/// - `1.2.3` - exact version (a bare version never floats, so partial
+///   ones like `1.2` are rejected; write `1.2.x` or `^1.2.0` instead)
+/// - `^1.2.3` / `~1.2.3` - compatible / patch-level ranges
+/// - `*`, `1.x`, `1.2.*` - wildcards
+/// - `>=1.2.0, <2.0.0` - comparison ranges (commas or spaces between comparators)
+///
+/// `operator` carries a `^` or `~` that `Dependency::parse` already stripped.
+pub fn parse_version_req(
+    constraint: &str,
+    operator: Option<&VersionOperator>,
+) -> ProtocolResult<VersionReq> {
+    let constraint = constraint.trim();
+
+    let normalized = match operator {
+        Some(VersionOperator::Caret) => format!("^{}", constraint),
+        Some(VersionOperator::Tilde) => format!("~{}", constraint),
+        None if is_bare_version(constraint) => {
+            let core = constraint.split(['-', '+']).next().unwrap_or_default();
+            if core.split('.').count() != 3 {
+                return Err(ProtocolError::InvalidVersion(format!(
+                    "Incomplete version '{}': give MAJOR.MINOR.PATCH, or a range such as '{}.x'",
+                    constraint, core
+                )));
+            }
+            format!("={}", constraint)
+        }
+        None => join_comparators(constraint),
+    };
+
+    VersionReq::parse(&normalized).map_err(|e| {
+        ProtocolError::InvalidVersion(format!("Invalid version range '{}': {}", constraint, e))
+    })
+}
+
+/// Select the highest version in `available` that satisfies `req`
+///
+/// Prerelease versions follow the usual SemVer range rules: they are only
+/// considered when the requirement itself names a prerelease on the same
+/// `MAJOR.MINOR.PATCH`, so `^1.0.0` never selects `1.1.0-beta.1`. Build
+/// metadata is ignored when matching and only breaks ties between versions
+/// of equal precedence.
+pub fn select_version<'a, I>(req: &VersionReq, available: I) -> Option<&'a Version>
+where
+    I: IntoIterator<Item = &'a Version>,
+{
+    available.into_iter().filter(|v| req.matches(v)).max()
+}
+
+/// A bare version such as `1.2.3` or `1.2` (no operator, wildcard or list)
+fn is_bare_version(constraint: &str) -> bool {
+    constraint.starts_with(|c: char| c.is_ascii_digit())
+        && !constraint.contains([',', ' ', '*'])
+        && !constraint
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .any(|part| part.eq_ignore_ascii_case("x"))
+}
+
+/// Rewrite space-separated comparators (`>=1.0.0 <2.0.0`) into the
+/// comma-separated form the semver crate expects
+fn join_comparators(constraint: &str) -> String {
+    let mut comparators: Vec<String> = Vec::new();
+    let mut pending_op = String::new();
+
+    for token in constraint
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+    {
+        if token
+            .chars()
+            .all(|c| matches!(c, '<' | '>' | '=' | '^' | '~'))
+        {
+            // Operator separated from its version, e.g. ">= 1.0.0"
+            pending_op.push_str(token);
+        } else {
+            comparators.push(format!("{}{}", pending_op, token));
+            pending_op.clear();
+        }
+    }
+
+    comparators.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(list: &[&str]) -> Vec<Version> {
+        list.iter().map(|v| Version::parse(v).unwrap()).collect()
+    }
+
+    fn best(constraint: &str, op: Option<VersionOperator>, list: &[&str]) -> Option<String> {
+        let req = parse_version_req(constraint, op.as_ref()).unwrap();
+        let available = versions(list);
+        select_version(&req, &available).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn test_exact_version_does_not_float() {
+        let available = ["1.0.0", "1.0.1", "1.2.0"];
+        assert_eq!(best("1.0.0", None, &available), Some("1.0.0".to_string()));
+        assert_eq!(best("1.1.0", None, &available), None);
+    }
+
+    #[test]
+    fn test_partial_bare_version_is_rejected() {
+        for partial in ["1", "1.2", "1.2-beta"] {
+            assert!(parse_version_req(partial, None).is_err(), "{}", partial);
+        }
+        assert_eq!(
+            best("1.0.x", None, &["1.0.0", "1.0.1"]),
+            Some("1.0.1".to_string())
+        );
+        assert!(parse_version_req("1.2", Some(&VersionOperator::Caret)).is_ok());
+    }
+
+    #[test]
+    fn test_caret_and_tilde() {
+        let available = ["1.0.0", "1.0.5", "1.4.2", "2.0.0"];
+        assert_eq!(
+            best("1.0.0", Some(VersionOperator::Caret), &available),
+            Some("1.4.2".to_string())
+        );
+        assert_eq!(
+            best("1.0.0", Some(VersionOperator::Tilde), &available),
+            Some("1.0.5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let available = ["1.0.0", "1.3.0", "2.1.0"];
+        assert_eq!(best("*", None, &available), Some("2.1.0".to_string()));
+        assert_eq!(best("1.x", None, &available), Some("1.3.0".to_string()));
+        assert_eq!(best("1.0.*", None, &available), Some("1.0.0".to_string()));
+    }
+
+    #[test]
+    fn test_comparison_ranges() {
+        let available = ["0.9.0", "1.5.0", "2.0.0"];
+        assert_eq!(
+            best(">=1.0.0, <2.0.0", None, &available),
+            Some("1.5.0".to_string())
+        );
+        assert_eq!(
+            best(">= 1.0.0 < 2.0.0", None, &available),
+            Some("1.5.0".to_string())
+        );
+        assert_eq!(best("<1.0.0", None, &available), Some("0.9.0".to_string()));
+    }
+
+    #[test]
+    fn test_prerelease_rules() {
+        let available = ["1.0.0", "1.1.0-beta.1", "1.1.0-beta.2"];
+        // Ranges without a prerelease never pick one
+        assert_eq!(
+            best("1.0.0", Some(VersionOperator::Caret), &available),
+            Some("1.0.0".to_string())
+        );
+        // Naming a prerelease opts into that MAJOR.MINOR.PATCH only
+        assert_eq!(
+            best("1.1.0-beta.1", Some(VersionOperator::Caret), &available),
+            Some("1.1.0-beta.2".to_string())
+        );
+        assert_eq!(
+            best("1.1.0-beta.1", None, &available),
+            Some("1.1.0-beta.1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_semver_2_precedence() {
+        let mut list = versions(&[
+            "1.0.0",
+            "1.0.0-rc.1",
+            "1.0.0-beta.11",
+            "1.0.0-beta.2",
+            "1.0.0-alpha.beta",
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-beta",
+        ]);
+        list.sort();
+        let sorted: Vec<String> = list.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            sorted,
+            [
+                "1.0.0-alpha",
+                "1.0.0-alpha.1",
+                "1.0.0-alpha.beta",
+                "1.0.0-beta",
+                "1.0.0-beta.2",
+                "1.0.0-beta.11",
+                "1.0.0-rc.1",
+                "1.0.0",
+            ]
+        );
+
+        assert_eq!(
+            parse_version("1.0.0+build.5").unwrap().build.as_str(),
+            "build.5"
+        );
+        assert_eq!(
+            best("1.0.0", None, &["1.0.0+build.5"]),
+            Some("1.0.0+build.5".to_string())
+        );
+        for invalid in ["1.0", "01.0.0", "v1.0.0", "1.0.0-", "1.0.0-beta..1"] {
+            assert!(parse_version(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_compare_versions_sorts_names() {
+        // Names that are not versions sort before every version
+        let mut names = ["1.10.0", "1.9.0", "latest", "1.9.0-rc.1"];
+        names.sort_by(|a, b| compare_versions(a, b));
+        assert_eq!(names, ["latest", "1.9.0-rc.1", "1.9.0", "1.10.0"]);
+    }
+
+    #[test]
+    fn test_invalid_range() {
+        assert!(parse_version_req("not-a-version", None).is_err());
+        assert!(parse_version_req("1.0.0", Some(&VersionOperator::Caret)).is_ok());
+    }
+}